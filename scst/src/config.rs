@@ -5,9 +5,12 @@ use std::path::Path;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{CopyManager, Device, Driver, Handler, IniGroup, Lun, Target};
+use crate::{
+    CopyManager, Device, Driver, Handler, HandlerType, IniGroup, Lun, Options, Target,
+    ValidationWarning, normalize_initiator,
+};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     version: String,
@@ -52,7 +55,13 @@ impl Config {
 
     /// create `Config` from yaml string
     pub fn from_str(s: &str) -> Result<Config> {
-        let config = serde_yml::from_str::<Config>(s)?;
+        let mut config = serde_yml::from_str::<Config>(s)?;
+        // the driver is named by its key if the name is omitted
+        for (name, dc) in config.drivers.iter_mut() {
+            if dc.name.is_empty() {
+                dc.name = name.clone();
+            }
+        }
         Ok(config)
     }
 
@@ -87,9 +96,82 @@ impl Config {
     pub fn version(&self) -> &str {
         &self.version
     }
+
+    /// the minimal `Config` holding the devices and targets which are added or changed compared
+    /// to the baseline. A changed target is taken as a whole, and a driver is kept if its
+    /// enabled state or any of its targets differ, so the delta applied on top of the baseline
+    /// yields this config.
+    pub fn delta(&self, baseline: &Config) -> Config {
+        let handlers = self
+            .handlers
+            .iter()
+            .filter_map(|(name, handler)| {
+                let base = baseline.handlers.get(name);
+                let devices: BTreeMap<String, DeviceCfg> = handler
+                    .devices
+                    .iter()
+                    .filter(|(dn, dc)| base.and_then(|b| b.devices.get(*dn)) != Some(*dc))
+                    .map(|(dn, dc)| (dn.clone(), dc.clone()))
+                    .collect();
+                if devices.is_empty() && base.is_some() {
+                    return None;
+                }
+
+                Some((
+                    name.clone(),
+                    HanderCfg {
+                        name: handler.name.clone(),
+                        devices,
+                    },
+                ))
+            })
+            .collect();
+
+        let drivers = self
+            .drivers
+            .iter()
+            .filter_map(|(name, driver)| {
+                let base = baseline.drivers.get(name);
+                let targets: BTreeMap<String, TargetCfg> = driver
+                    .targets
+                    .iter()
+                    .filter(|(tn, tc)| base.and_then(|b| b.targets.get(*tn)) != Some(*tc))
+                    .map(|(tn, tc)| (tn.clone(), tc.clone()))
+                    .collect();
+                let enabled_changed = base.map_or(true, |b| b.enabled != driver.enabled);
+                if targets.is_empty() && !enabled_changed {
+                    return None;
+                }
+
+                Some((
+                    name.clone(),
+                    DriverCfg {
+                        targets,
+                        ..driver.clone()
+                    },
+                ))
+            })
+            .collect();
+
+        Config {
+            version: self.version.clone(),
+            handlers,
+            drivers,
+        }
+    }
+
+    /// checks the config on its own for the mistakes which still apply, like a driver enabled
+    /// without any target. See `Scst::validate_cfg()` for the checks against the live system.
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        self.drivers
+            .iter()
+            .filter(|(_, dc)| dc.enabled() == 1 && dc.targets.is_empty())
+            .map(|(name, _)| ValidationWarning::EmptyEnabledDriver(name.clone()))
+            .collect()
+    }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HanderCfg {
     #[serde(default)]
     name: String,
@@ -125,13 +207,13 @@ impl From<&Handler> for HanderCfg {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DeviceCfg {
     #[serde(default)]
     name: String,
     #[serde(default)]
     filename: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
     size: usize,
 }
 
@@ -144,28 +226,42 @@ impl DeviceCfg {
         &self.filename
     }
 
+    /// the device size, 0 for a pass-through device whose size follows the hardware.
     pub fn size(&self) -> usize {
         self.size
     }
 }
 
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
 impl From<&Device> for DeviceCfg {
     fn from(value: &Device) -> Self {
+        let pass_through = matches!(
+            HandlerType::from_name(value.handler()),
+            HandlerType::Dev | HandlerType::Changer | HandlerType::Processor
+        );
+
         DeviceCfg {
             name: value.name().to_string(),
             filename: value.filename().to_string_lossy().to_string(),
-            size: value.size(),
+            size: if pass_through { 0 } else { value.size() },
         }
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DriverCfg {
     #[serde(default)]
     name: String,
     #[serde(default)]
     enabled: Option<i8>,
     #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    open_state: Option<String>,
+    #[serde(default)]
     targets: BTreeMap<String, TargetCfg>,
 }
 
@@ -178,6 +274,16 @@ impl DriverCfg {
         self.enabled.unwrap_or(0)
     }
 
+    /// the driver version which exported the config, it's read-only and never applied.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// the driver open state when the config exported, it's read-only and never applied.
+    pub fn open_state(&self) -> Option<&str> {
+        self.open_state.as_deref()
+    }
+
     pub fn targets(&self) -> Vec<&TargetCfg> {
         self.targets.values().collect()
     }
@@ -197,6 +303,8 @@ impl From<&Driver> for DriverCfg {
         DriverCfg {
             name: value.name().to_string(),
             enabled: Some(value.enabled_i8()),
+            version: Some(value.version().to_string()),
+            open_state: Some(value.open_state().to_string()),
             targets,
         }
     }
@@ -212,12 +320,14 @@ impl From<&CopyManager> for DriverCfg {
         DriverCfg {
             name: value.name().to_string(),
             enabled: None,
+            version: None,
+            open_state: None,
             targets,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TargetCfg {
     #[serde(default)]
     name: String,
@@ -284,7 +394,7 @@ impl From<&Target> for TargetCfg {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IniGroupCfg {
     #[serde(default)]
     name: String,
@@ -324,11 +434,13 @@ impl From<&IniGroup> for IniGroupCfg {
             })
             .collect();
 
-        let initiators = value
-            .initiators()
-            .iter()
-            .filter_map(|s| Some(s.clone()))
-            .collect();
+        let mut initiators = Vec::new();
+        for ini in value.initiators() {
+            let ini = normalize_initiator(ini);
+            if !initiators.contains(&ini) {
+                initiators.push(ini);
+            }
+        }
 
         IniGroupCfg {
             name: value.name().to_string(),
@@ -338,13 +450,16 @@ impl From<&IniGroup> for IniGroupCfg {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LunCfg {
     #[serde(default)]
     id: u64,
 
     #[serde(default)]
     device: String,
+
+    #[serde(default)]
+    read_only: i8,
 }
 
 impl LunCfg {
@@ -355,6 +470,19 @@ impl LunCfg {
     pub fn device(&self) -> &str {
         &self.device
     }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only == 1
+    }
+
+    /// the options to create the LUN with.
+    pub(crate) fn options(&self) -> Options {
+        let mut opts = Options::new();
+        if self.read_only() {
+            opts.insert("read_only", "1");
+        }
+        opts
+    }
 }
 
 impl From<&Lun> for LunCfg {
@@ -362,6 +490,7 @@ impl From<&Lun> for LunCfg {
         LunCfg {
             id: value.id(),
             device: value.device().to_string(),
+            read_only: value.read_only() as i8,
         }
     }
 }
@@ -370,7 +499,9 @@ impl From<&Lun> for LunCfg {
 mod test {
     use anyhow::Result;
 
-    use super::Config;
+    use super::*;
+    use crate::Layer;
+    use crate::testutil::Fixture;
 
     #[test]
     fn test_config_from_yaml() -> Result<()> {
@@ -418,4 +549,91 @@ drivers:
         Config::from_str(s)?;
         Ok(())
     }
+
+    #[test]
+    fn test_driver_cfg_info() -> Result<()> {
+        let fx = Fixture::new("test_driver_cfg_info");
+        fx.driver("iscsi");
+
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/iscsi"))?;
+
+        let dc = DriverCfg::from(&driver);
+        assert_eq!(dc.version(), Some("3.7.0"));
+        assert_eq!(dc.open_state(), Some("open"));
+
+        let s = serde_yml::to_string(&dc)?;
+        assert!(s.contains("version: '3.7.0'"));
+        assert!(s.contains("open_state: open"));
+
+        let dc = serde_yml::from_str::<DriverCfg>(&s)?;
+        assert_eq!(dc.version(), Some("3.7.0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_lun_read_only() -> Result<()> {
+        let fx = Fixture::new("test_group_lun_read_only");
+        let grp = "targets/iscsi/iqn.2018-11.com.vine:vol/ini_groups/vol";
+        fx.group("targets/iscsi/iqn.2018-11.com.vine:vol", "vol", &[])
+            .lun(grp, 0, "vol")
+            .file(format!("{}/luns/0/read_only", grp), "1\n");
+
+        let mut group = IniGroup::default();
+        group.load(fx.path(grp))?;
+
+        let gc = IniGroupCfg::from(&group);
+        let s = serde_yml::to_string(&gc)?;
+        let gc = serde_yml::from_str::<IniGroupCfg>(&s)?;
+        assert!(gc.luns()[0].read_only());
+        assert_eq!(
+            gc.luns()[0].options().pack(),
+            Some("read_only=1".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_empty_enabled_driver() -> Result<()> {
+        let s = r#"
+version: '3.7.0'
+drivers:
+  iscsi:
+    enabled: 1
+    targets: {}
+  qla2x00t:
+    enabled: 0
+    targets: {}
+"#;
+        let cfg = Config::from_str(s)?;
+        assert_eq!(
+            cfg.validate(),
+            vec![ValidationWarning::EmptyEnabledDriver("iscsi".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn device_cfg_pass_through_size() -> Result<()> {
+        let fx = Fixture::new("device_cfg_pass_through_size");
+        fx.device("dev_disk", "2:0:0:0", "")
+            .device("vdisk_fileio", "vol", "/tank/vol.img");
+
+        let mut disk = Device::default();
+        disk.load(fx.path("handlers/dev_disk/2:0:0:0"))?;
+        let dc = DeviceCfg::from(&disk);
+        assert_eq!(dc.size(), 0);
+        assert!(!serde_yml::to_string(&dc)?.contains("size"));
+
+        let mut vol = Device::default();
+        vol.load(fx.path("handlers/vdisk_fileio/vol"))?;
+        let dc = DeviceCfg::from(&vol);
+        assert_eq!(dc.size(), 10737418240);
+        assert!(serde_yml::to_string(&dc)?.contains("size: 10737418240"));
+
+        Ok(())
+    }
 }
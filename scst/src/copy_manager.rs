@@ -4,7 +4,7 @@ use std::path::Path;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{Layer, Target};
+use crate::{Layer, Lun, Options, Target};
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CopyManager {
@@ -23,6 +23,29 @@ impl CopyManager {
     pub fn tgt(&self) -> &Target {
         &self.tgt
     }
+
+    /// the LUNs of the devices available for local copy operations.
+    pub fn luns(&self) -> Vec<&Lun> {
+        self.tgt.luns()
+    }
+
+    pub fn get_lun<S: AsRef<str>>(&self, lun_id: S) -> Result<&Lun> {
+        self.tgt.get_lun(lun_id)
+    }
+
+    /// makes the device available for EXTENDED COPY by a LUN of `copy_manager_tgt`.
+    pub fn add_lun<S: AsRef<str>>(
+        &mut self,
+        device: S,
+        lun_id: u64,
+        options: &Options,
+    ) -> Result<()> {
+        self.tgt.add_lun(device, lun_id, options)
+    }
+
+    pub fn del_lun(&mut self, lun_id: u64) -> Result<()> {
+        self.tgt.del_lun(lun_id)
+    }
 }
 
 impl Layer for CopyManager {
@@ -47,3 +70,55 @@ impl Layer for CopyManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::Scst;
+    use crate::testutil::{Fixture, echoed_cmds};
+
+    #[test]
+    fn copy_manager_luns() -> Result<()> {
+        let fx = Fixture::new("copy_manager_luns");
+        let tgt = "targets/copy_manager/copy_manager_tgt";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_fileio", "vol2", "/tank/vol2.img")
+            .lun(tgt, 0, "vol1")
+            .lun(tgt, 1, "vol2");
+        let mut copy_manager = CopyManager::default();
+        copy_manager.load(fx.path("targets/copy_manager"))?;
+
+        let devices = copy_manager
+            .luns()
+            .iter()
+            .map(|lun| lun.device())
+            .collect::<Vec<&str>>();
+        assert_eq!(devices, vec!["vol1", "vol2"]);
+        assert_eq!(copy_manager.get_lun("lun1")?.device(), "vol2");
+        assert!(copy_manager.get_lun("lun2").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_manager_add_del_lun() -> Result<()> {
+        let fx = Fixture::new("copy_manager_add_del_lun");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .simulate();
+        let mut scst = Scst::init_at(fx.root())?;
+
+        let copy_manager = scst.copy_manager_mut();
+        copy_manager.add_lun("vol1", 0, &Options::new())?;
+        assert_eq!(copy_manager.get_lun("lun0")?.device(), "vol1");
+
+        copy_manager.del_lun(0)?;
+        assert!(copy_manager.luns().is_empty());
+        assert_eq!(echoed_cmds(), vec!["add vol1 0", "del 0"]);
+
+        Ok(())
+    }
+}
@@ -1,10 +1,152 @@
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{Layer, read_fl, read_link};
+use crate::handler::DEVICE_CREATE_PARAMS;
+use crate::{
+    Layer, Options, Scst, ScstError, echo, fail_context, is_static_attr, read_bool, read_extra,
+    read_fl, read_link, read_mgmt_help,
+};
+
+static ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// the attributes captured by typed fields
+static DEVICE_ATTRS: &[&str] = &[
+    "filename",
+    "active",
+    "read_only",
+    "size",
+    "blocksize",
+    "t10_dev_id",
+    "t10_vend_id",
+    "prod_id",
+    "usn",
+];
+// the attributes fixed when the device is created
+static DEVICE_STATIC_ATTRS: &[&str] = &["handler", "type", "blocksize", "size", "exported"];
+
+/// a token of the `dif_mode` device attribute, the tokens are combined by `|`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifMode {
+    /// `tgt`, the target hardware checks the protection information.
+    Tgt,
+    /// `scst`, SCST checks the protection information.
+    Scst,
+    /// `dev_check`, the device checks the protection information.
+    DevCheck,
+    /// `dev_store`, the device stores the protection information, a vdisk in `dif_filename`.
+    DevStore,
+}
+
+impl DifMode {
+    pub fn from_name<S: AsRef<str>>(name: S) -> Option<Self> {
+        match name.as_ref() {
+            "tgt" => Some(DifMode::Tgt),
+            "scst" => Some(DifMode::Scst),
+            "dev_check" => Some(DifMode::DevCheck),
+            "dev_store" => Some(DifMode::DevStore),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DifMode::Tgt => "tgt",
+            DifMode::Scst => "scst",
+            DifMode::DevCheck => "dev_check",
+            DifMode::DevStore => "dev_store",
+        }
+    }
+}
+
+/// the T10 protection type of the `dif_type` device attribute.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifType {
+    /// no protection information.
+    Type0,
+    Type1,
+    Type2,
+    Type3,
+}
+
+impl DifType {
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DifType::Type0),
+            1 => Some(DifType::Type1),
+            2 => Some(DifType::Type2),
+            3 => Some(DifType::Type3),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// checks the DIF attributes of a device to create: the tokens of `dif_mode` and `dif_type`
+/// are known, a `dif_mode` comes with a protection type, and `dev_store` with a `dif_filename`.
+pub(crate) fn check_dif(options: &Options) -> Result<()> {
+    let modes = match options.get("dif_mode") {
+        Some(mode) => mode
+            .split('|')
+            .map(|token| {
+                DifMode::from_name(token)
+                    .ok_or_else(|| ScstError::DeviceBadDif(format!("unknown dif_mode '{}'", token)))
+            })
+            .collect::<std::result::Result<Vec<DifMode>, ScstError>>()?,
+        None => Vec::new(),
+    };
+    let dif_type = match options.get("dif_type") {
+        Some(value) => value
+            .parse::<u8>()
+            .ok()
+            .and_then(DifType::from_value)
+            .ok_or_else(|| ScstError::DeviceBadDif(format!("unknown dif_type '{}'", value)))?,
+        None => DifType::Type0,
+    };
+
+    if !modes.is_empty() && dif_type == DifType::Type0 {
+        anyhow::bail!(ScstError::DeviceBadDif(
+            "dif_mode needs a dif_type of 1 to 3".to_string()
+        ))
+    }
+    if modes.is_empty() && dif_type != DifType::Type0 {
+        anyhow::bail!(ScstError::DeviceBadDif(
+            "dif_type needs a dif_mode".to_string()
+        ))
+    }
+    let dev_store = modes.contains(&DifMode::DevStore);
+    match options.get("dif_filename") {
+        None if dev_store => anyhow::bail!(ScstError::DeviceBadDif(
+            "dif_mode dev_store needs a dif_filename".to_string()
+        )),
+        Some(_) if !dev_store => anyhow::bail!(ScstError::DeviceBadDif(
+            "dif_filename needs dif_mode dev_store".to_string()
+        )),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// the state of the backing file of a device, see `Device::verify_backing()`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackingStatus {
+    Ok,
+    /// the filename doesn't exist anymore, or it's a dangling symlink.
+    Missing,
+    /// the filename resolves to another path than when the device was loaded, like a
+    /// symlink re-pointed since. The file kept open by SCST isn't known, so a change made
+    /// before the load isn't detected.
+    Mismatch,
+}
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Device {
@@ -17,6 +159,18 @@ pub struct Device {
     read_only: i8,
     size: usize,
     blocksize: u32,
+    t10_dev_id: String,
+    #[serde(default)]
+    t10_vend_id: String,
+    #[serde(default)]
+    prod_id: String,
+    usn: String,
+    #[serde(default)]
+    extra: BTreeMap<String, String>,
+
+    // the filename resolved when loaded
+    #[serde(skip)]
+    backing: Option<PathBuf>,
 }
 
 impl Device {
@@ -47,6 +201,152 @@ impl Device {
     pub fn blocksize(&self) -> u32 {
         self.blocksize
     }
+
+    pub fn t10_dev_id(&self) -> &str {
+        &self.t10_dev_id
+    }
+
+    /// the vendor identification reported in INQUIRY, empty if the handler omits it.
+    pub fn t10_vend_id(&self) -> &str {
+        &self.t10_vend_id
+    }
+
+    /// the product identification reported in INQUIRY, empty if the handler omits it.
+    pub fn prod_id(&self) -> &str {
+        &self.prod_id
+    }
+
+    pub fn usn(&self) -> &str {
+        &self.usn
+    }
+
+    /// the readable attributes without typed support, like `numa_node_id` or the handler
+    /// specific ones.
+    pub fn extra(&self) -> &BTreeMap<String, String> {
+        &self.extra
+    }
+
+    /// the raw usage text of the handler `mgmt` of the device, like the parameters of
+    /// `add_device`. The device directory has no `mgmt` of its own.
+    pub fn mgmt_help(&self) -> Result<String> {
+        read_mgmt_help(self.root().join("handler"))
+    }
+
+    /// checks the filename still exists and resolves to the path it did when the device was
+    /// loaded. It compares against the path canonicalized at load, not the file kept open by
+    /// SCST, so a filename already stale when loaded reports `Ok`; reload the device to
+    /// take a new baseline.
+    pub fn verify_backing(&self) -> Result<BackingStatus> {
+        let resolved = match fs::canonicalize(self.filename()) {
+            Ok(path) => path,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(BackingStatus::Missing);
+            }
+            Err(e) => return Err(ScstError::Io(e).into()),
+        };
+
+        match &self.backing {
+            Some(backing) if *backing != resolved => Ok(BackingStatus::Mismatch),
+            _ => Ok(BackingStatus::Ok),
+        }
+    }
+}
+
+impl Device {
+    /// what it takes to create the device again with the same identity: the `add_device`
+    /// options but `filename`, and the other writable attributes to set afterwards, like
+    /// `t10_dev_id` or `usn`.
+    pub(crate) fn recreate_options(&self) -> (Options, BTreeMap<String, String>) {
+        let mut options = Options::new();
+        let mut attrs = BTreeMap::new();
+        for (attr, value) in read_extra(self.root(), &[]) {
+            if attr == "filename" {
+                continue;
+            }
+            if DEVICE_CREATE_PARAMS.contains(&attr.as_str()) {
+                options.insert(attr, value);
+            } else if !DEVICE_STATIC_ATTRS.contains(&attr.as_str())
+                && !is_static_attr(self.root().join(&attr))
+            {
+                attrs.insert(attr, value);
+            }
+        }
+
+        (options, attrs)
+    }
+
+    /// a LUN of the iscsi targets or their groups maps the device.
+    pub fn is_in_use(&self, scst: &Scst) -> bool {
+        !scst.targets_exposing(&self.name).is_empty()
+    }
+
+    /// polls the `active` attribute until the device is active, newly created devices may
+    /// report inactive briefly. Returns `ScstError::DeviceNotActive` when `timeout` elapses.
+    pub fn wait_active(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if read_bool(self.root().join("active"))? {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(ScstError::DeviceNotActive(self.name.clone()))
+            }
+            thread::sleep(
+                ACTIVE_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+            );
+        }
+    }
+}
+
+impl Device {
+    /// sets the attribute of device by writing its sysfs file, like `read_only` or `nv_cache`,
+    /// then re-reads the device. `ScstError::DeviceAttrStatic` returns for the attributes fixed
+    /// on creation, and `ScstError::DeviceBadAttr` for the unknown ones.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let device = scst
+    ///         .get_handler_mut("vdisk_blockio")?
+    ///         .get_device_mut("vol")?;
+    ///     device.set_attribute("read_only", "1")?;
+    ///     assert!(device.read_only());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_attribute<S: AsRef<str>>(&mut self, attr: S, value: S) -> Result<()> {
+        let attr = attr.as_ref();
+        let path = self.root().join(attr);
+        if DEVICE_STATIC_ATTRS.contains(&attr) {
+            anyhow::bail!(ScstError::DeviceAttrStatic(attr.to_string()))
+        }
+        if !path.is_file() {
+            anyhow::bail!(ScstError::DeviceBadAttr)
+        }
+
+        echo(path, value.as_ref().into())
+            .map_err(|e| fail_context(e, ScstError::DeviceSetAttrFail(attr.to_string())))?;
+
+        let root = self.root().to_path_buf();
+        self.load(root)
+    }
+}
+
+impl Device {
+    /// makes scst re-read the size of the backing store, like after growing a zvol, then
+    /// updates `size()`. It's safe to call repeatedly.
+    pub fn resync_size(&mut self) -> Result<()> {
+        echo(self.root().join("resync_size"), "1".into()).map_err(|e| {
+            fail_context(e, ScstError::DeviceSetAttrFail("resync_size".to_string()))
+        })?;
+        self.size = read_fl(self.root().join("size"))?.parse::<usize>()?;
+
+        Ok(())
+    }
 }
 
 impl Layer for Device {
@@ -71,14 +371,199 @@ impl Layer for Device {
             .to_string_lossy()
             .to_string();
         self.filename = read_fl(root_ref.join("filename"))?;
-        self.active = read_fl(root_ref.join("active"))?.parse::<i8>()?;
-        self.read_only = read_fl(root_ref.join("read_only"))?.parse::<i8>()?;
+        self.active = read_bool(root_ref.join("active"))? as i8;
+        self.read_only = read_bool(root_ref.join("read_only"))? as i8;
         self.size = read_fl(root_ref.join("size"))?.parse::<usize>()?;
         self.blocksize = read_fl(root_ref.join("blocksize"))?.parse::<u32>()?;
+        self.t10_dev_id = read_fl(root_ref.join("t10_dev_id")).unwrap_or_default();
+        self.t10_vend_id = read_fl(root_ref.join("t10_vend_id")).unwrap_or_default();
+        self.prod_id = read_fl(root_ref.join("prod_id")).unwrap_or_default();
+        self.usn = read_fl(root_ref.join("usn")).unwrap_or_default();
+        self.extra = read_extra(root_ref, DEVICE_ATTRS);
+        self.backing = fs::canonicalize(&self.filename).ok();
 
         Ok(())
     }
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+    use crate::testutil::{Fixture, echoed_cmds};
+
+    #[test]
+    fn wait_active() -> Result<()> {
+        let fx = Fixture::new("wait_active");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/vol/active", "0\n");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol"))?;
+        assert!(!device.is_active());
+
+        let err = device.wait_active(Duration::from_millis(10)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceNotActive(_))
+        ));
+
+        let active = fx.path("handlers/vdisk_blockio/vol/active");
+        let flip = thread::spawn(move || {
+            thread::sleep(ACTIVE_POLL_INTERVAL / 2);
+            std::fs::write(active, "1\n").unwrap();
+        });
+        device.wait_active(Duration::from_secs(5))?;
+        flip.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_extra() -> Result<()> {
+        let fx = Fixture::new("device_load_extra");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/vol/numa_node_id", "-1\n")
+            .file("handlers/vdisk_blockio/vol/nv_cache", "0\n[key]\n")
+            .file("handlers/vdisk_blockio/vol/usn", "7f3a\n")
+            .dir("handlers/vdisk_blockio/vol/exported");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol"))?;
+
+        let extra = device.extra();
+        assert_eq!(extra.get("numa_node_id").map(|v| v.as_str()), Some("-1"));
+        assert_eq!(extra.get("nv_cache").map(|v| v.as_str()), Some("0"));
+        assert!(!extra.contains_key("usn"));
+        assert!(!extra.contains_key("filename"));
+        assert!(!extra.contains_key("handler"));
+        assert!(!extra.contains_key("exported"));
+        assert_eq!(device.usn(), "7f3a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_attribute() -> Result<()> {
+        let fx = Fixture::new("device_set_attribute");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/vol/nv_cache", "0\n");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol"))?;
+        assert!(!device.read_only());
+
+        device.set_attribute("read_only", "1")?;
+        assert!(device.read_only());
+        device.set_attribute("nv_cache", "1")?;
+        assert_eq!(
+            device.extra().get("nv_cache").map(|v| v.as_str()),
+            Some("1")
+        );
+
+        let err = device.set_attribute("blocksize", "4096").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceAttrStatic(a)) if a == "blocksize"
+        ));
+        let err = device.set_attribute("missing", "1").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceBadAttr)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_identity() -> Result<()> {
+        let fx = Fixture::new("device_load_identity");
+        fx.device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .file(
+                "handlers/vdisk_blockio/vol1/t10_dev_id",
+                "vol1-7f3a\n[key]\n",
+            )
+            .file("handlers/vdisk_blockio/vol1/t10_vend_id", "SCST_BIO\n")
+            .file("handlers/vdisk_blockio/vol1/prod_id", "vol1\n")
+            .file("handlers/vdisk_blockio/vol1/usn", "7f3a\n");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol1"))?;
+        assert_eq!(device.t10_dev_id(), "vol1-7f3a");
+        assert_eq!(device.t10_vend_id(), "SCST_BIO");
+        assert_eq!(device.prod_id(), "vol1");
+        assert_eq!(device.usn(), "7f3a");
+        assert!(device.extra().is_empty());
+
+        // older handlers omit the identity attributes
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol2"))?;
+        assert_eq!(device.t10_vend_id(), "");
+        assert_eq!(device.prod_id(), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn resync_size() -> Result<()> {
+        let fx = Fixture::new("device_resync_size");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/vol/resync_size", "");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol"))?;
+        assert_eq!(device.size(), 10737418240);
+
+        fx.file("handlers/vdisk_blockio/vol/size", "21474836480\n");
+        device.resync_size()?;
+        device.resync_size()?;
+        assert_eq!(device.size(), 21474836480);
+        assert_eq!(echoed_cmds(), vec!["1", "1"]);
+
+        fx.remove("handlers/vdisk_blockio/vol/resync_size")
+            .dir("handlers/vdisk_blockio/vol/resync_size");
+        let err = device.resync_size().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceSetAttrFail(a)) if a == "resync_size"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_backing() -> Result<()> {
+        let fx = Fixture::new("verify_backing");
+        let filename = fx.path("disks/vol.link");
+        fx.file("disks/vol.img", "")
+            .file("disks/moved.img", "")
+            .link("disks/vol.link", fx.path("disks/vol.img"))
+            .device("vdisk_fileio", "vol", filename.to_string_lossy().as_ref());
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_fileio/vol"))?;
+        assert_eq!(device.verify_backing()?, BackingStatus::Ok);
+
+        fx.link("disks/vol.link", fx.path("disks/moved.img"));
+        assert_eq!(device.verify_backing()?, BackingStatus::Mismatch);
+
+        fx.remove("disks/moved.img");
+        assert_eq!(device.verify_backing()?, BackingStatus::Missing);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mgmt_help() -> Result<()> {
+        let fx = Fixture::new("device_mgmt_help");
+        let usage = "Usage: echo \"add_device device_name [parameters]\" >mgmt\n";
+        fx.device("vdisk_fileio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_fileio/mgmt", usage);
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_fileio/vol"))?;
+        assert_eq!(device.mgmt_help()?, usage);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Layer, ScstError, read_dir, read_fl};
+
+static DGRP_DEVICE: &str = "devices";
+pub(crate) static DGRP_TARGET_GROUP: &str = "target_groups";
+
+/// ALUA device group
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct DeviceGroup {
+    #[serde(skip)]
+    root: String,
+    name: String,
+
+    devices: Vec<String>,
+    target_groups: BTreeMap<String, TargetGroup>,
+}
+
+impl DeviceGroup {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn devices(&self) -> &[String] {
+        &self.devices
+    }
+
+    pub fn target_groups(&self) -> Vec<&TargetGroup> {
+        self.target_groups.values().collect()
+    }
+
+    pub fn get_target_group<S: AsRef<str>>(&self, name: S) -> Result<&TargetGroup> {
+        self.target_groups
+            .get(name.as_ref())
+            .context(ScstError::NoTargetGroup(name.as_ref().to_string()))
+    }
+}
+
+impl Layer for DeviceGroup {
+    fn root(&self) -> &Path {
+        Path::new(&self.root)
+    }
+
+    fn load<P: AsRef<Path>>(&mut self, root: P) -> Result<()> {
+        let root_ref = root.as_ref();
+        self.root = root_ref.to_string_lossy().to_string();
+        self.name = root_ref
+            .file_name()
+            .unwrap_or(OsStr::new(""))
+            .to_string_lossy()
+            .to_string();
+
+        self.devices = read_dir(root_ref.join(DGRP_DEVICE))?
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.file_name() != "mgmt")
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        self.devices.sort();
+
+        // traverse target groups
+        self.target_groups = read_dir(root_ref.join(DGRP_TARGET_GROUP))?
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| {
+                let mut tg = TargetGroup::default();
+                tg.load(entry.path()).ok();
+                (tg.name().to_string(), tg)
+            })
+            .collect();
+
+        Ok(())
+    }
+}
+
+/// ALUA target group of a device group
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TargetGroup {
+    #[serde(skip)]
+    root: String,
+    name: String,
+    group_id: u64,
+    state: String,
+
+    targets: Vec<String>,
+}
+
+impl TargetGroup {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn group_id(&self) -> u64 {
+        self.group_id
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+}
+
+impl Layer for TargetGroup {
+    fn root(&self) -> &Path {
+        Path::new(&self.root)
+    }
+
+    fn load<P: AsRef<Path>>(&mut self, root: P) -> Result<()> {
+        let root_ref = root.as_ref();
+        self.root = root_ref.to_string_lossy().to_string();
+        self.name = root_ref
+            .file_name()
+            .unwrap_or(OsStr::new(""))
+            .to_string_lossy()
+            .to_string();
+        self.group_id = read_fl(root_ref.join("group_id"))?.parse::<u64>()?;
+        self.state = read_fl(root_ref.join("state")).unwrap_or_default();
+
+        self.targets = read_dir(root_ref)?
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        self.targets.sort();
+
+        Ok(())
+    }
+}
@@ -1,9 +1,113 @@
 use thiserror::Error;
 
+/// a failure of applying an object from `Config`, `object` is the path of the object like
+/// `iscsi/iqn.2018-11.com.vine:vol/lun0`.
+#[derive(Error, Debug)]
+#[error("Failed to apply '{object}': {error}")]
+pub struct ApplyError {
+    object: String,
+    error: anyhow::Error,
+}
+
+impl ApplyError {
+    pub(crate) fn new<S: AsRef<str>>(object: S, error: anyhow::Error) -> Self {
+        ApplyError {
+            object: object.as_ref().to_string(),
+            error,
+        }
+    }
+
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    pub fn error(&self) -> &anyhow::Error {
+        &self.error
+    }
+}
+
+/// the outcome of a bulk operation, which goes on after a failed item.
+#[derive(Debug)]
+pub struct BatchResult<T> {
+    succeeded: Vec<T>,
+    failed: Vec<(T, ScstError)>,
+}
+
+impl<T> BatchResult<T> {
+    pub fn new() -> Self {
+        BatchResult {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// records the result of an item.
+    pub(crate) fn push(&mut self, item: T, result: anyhow::Result<()>) {
+        match result {
+            Ok(()) => self.succeeded.push(item),
+            Err(e) => self.failed.push((item, ScstError::from_anyhow(e))),
+        }
+    }
+
+    pub fn succeeded(&self) -> &[T] {
+        &self.succeeded
+    }
+
+    pub fn failed(&self) -> &[(T, ScstError)] {
+        &self.failed
+    }
+
+    /// all items succeeded.
+    pub fn all_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// the succeeded items, or the failed ones if any failed.
+    pub fn into_result(self) -> Result<Vec<T>, Vec<(T, ScstError)>> {
+        if self.failed.is_empty() {
+            return Ok(self.succeeded);
+        }
+
+        Err(self.failed)
+    }
+}
+
+impl<T> Default for BatchResult<T> {
+    fn default() -> Self {
+        BatchResult::new()
+    }
+}
+
+/// a problem of `Config` found by validating it against the live system.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Handler '{0}' isn't loaded.")]
+    NoHandler(String),
+    #[error("Driver '{0}' isn't loaded.")]
+    NoDriver(String),
+    #[error("Device '{device}' referenced by '{object}' neither exists nor is configured.")]
+    NoDevice { object: String, device: String },
+    #[error("Device '{device}' is read-only, set 'read_only' explicitly for '{object}'.")]
+    LunReadOnlyDevice { object: String, device: String },
+    #[error("Parameter '{param}' of '{object}' isn't supported by its handler.")]
+    UnsupportedParam { object: String, param: String },
+    #[error("Attribute '{attr}' of '{object}' isn't exposed by the live system.")]
+    UnsupportedAttr { object: String, attr: String },
+}
+
+/// a suspicious but applicable part of `Config`, it may be intentional.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    #[error("Driver '{0}' is enabled without any target.")]
+    EmptyEnabledDriver(String),
+}
+
 #[derive(Error, Debug)]
 pub enum ScstError {
     #[error("No such SCST module exists")]
     NoModule,
+    #[error("SCST is locked by another process, lock file '{0}'.")]
+    Locked(String),
     #[error("A fatal error occured. See \"dmesg\" for more information.")]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -15,6 +119,8 @@ pub enum ScstError {
     AttrStatic(String),
     #[error("Failed to set a SCST attribute '{0}'. See \"demsg\" for more information.")]
     SetattrFail(String),
+    #[error("Bad boolean value '{value}' of attribute '{attr}', 0 or 1 expected.")]
+    BadBool { attr: String, value: String },
 
     #[error("No such handler '{0}' exists.")]
     NoHandler(String),
@@ -33,12 +139,22 @@ pub enum ScstError {
     DeviceAddFail { name: String, e: anyhow::Error },
     #[error("Failed to remove device '{0}'. See \"dmesg\" for more information.")]
     DeviceRemFail(String),
+    #[error("Device '{device}' is still used by LUN '{lun}'.")]
+    DeviceInUse { device: String, lun: String },
     #[error("Bad attributes given for device.")]
     DeviceBadAttr,
     #[error("Device attribute '{0}' specified is static.")]
     DeviceAttrStatic(String),
     #[error("Failed to set device attribute '{0}'. See \"dmesg\" for more information.")]
     DeviceSetAttrFail(String),
+    #[error("Device '{0}' isn't active.")]
+    DeviceNotActive(String),
+    #[error("Invalid name '{0}' given, only alphanumerics, '_', '-' and '.' are allowed.")]
+    InvalidName(String),
+    #[error("Backing file '{filename}' already exists with another size {size}.")]
+    DeviceFileSize { filename: String, size: u64 },
+    #[error("Bad DIF attributes given for device: {0}.")]
+    DeviceBadDif(String),
 
     #[error("No such driver '{0}' exists.")]
     NoDriver(String),
@@ -79,6 +195,10 @@ pub enum ScstError {
     TargetLunExists(String),
     #[error("Bad attributes given for target.")]
     TargetBadAttrs,
+    #[error("Bad portal address '{0}' given for target.")]
+    TargetBadPortal(String),
+    #[error("Bad value '{value}' given for iSCSI parameter '{name}' of target.")]
+    TargetBadParam { name: String, value: u32 },
     #[error("Target attribute '{0}' specified is static.")]
     TargetBadAttr(String),
     #[error("Failed to set target attribute '{0}'. See \"dmesg\" for more information.")]
@@ -137,6 +257,12 @@ pub enum ScstError {
     LunAttrStatic(String),
     #[error("Failed to set LUN attribute '{0}'. See \"dmesg\" for more information.")]
     LunSetAttrFail(String),
+    #[error("Device '{0}' is read-only, set 'read_only' explicitly for LUN.")]
+    LunReadOnlyDevice(String),
+    #[error("Commands in flight on LUN '{0}' didn't drain in time.")]
+    LunDrainTimeout(String),
+    #[error("Device '{0}' is only exported by this LUN and in use by active sessions.")]
+    LastPathToDevice(String),
 
     #[error("Bad attributes for initiator.")]
     IniBadAttrs,
@@ -149,6 +275,15 @@ pub enum ScstError {
     NoSession,
     #[error("Failed to close session.")]
     SessionCloseFail,
+    #[error("Failed to count sessions of target '{target}', {counted} counted before it.")]
+    SessionCountFail { target: String, counted: usize },
+
+    #[error("No such device group '{0}' exists.")]
+    NoDeviceGroup(String),
+    #[error("No such target group '{0}' exists within device group.")]
+    NoTargetGroup(String),
+    #[error("Failed to add target '{0}' to target group.")]
+    TargetGroupAddTgtFail(String),
     /*
 
     (SCST_C_DEV_GRP_NO_GROUP)     => 'No such device group exists.',
@@ -185,5 +320,48 @@ pub enum ScstError {
          */
 }
 
+impl ScstError {
+    /// takes the `ScstError` inside of the `anyhow::Error`, others are wrapped as
+    /// `ScstError::Unknown`.
+    pub(crate) fn from_anyhow(e: anyhow::Error) -> Self {
+        match e.downcast::<ScstError>() {
+            Ok(e) => e,
+            Err(e) => ScstError::Unknown(e),
+        }
+    }
+}
+
 unsafe impl Sync for ScstError {}
 unsafe impl Send for ScstError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batch_result() {
+        let mut batch = BatchResult::new();
+        batch.push("iqn.a", Ok(()));
+        batch.push(
+            "iqn.b",
+            Err(ScstError::GroupIniExists("iqn.b".to_string()).into()),
+        );
+        batch.push("iqn.c", Err(anyhow::anyhow!("write failed")));
+        batch.push("iqn.d", Ok(()));
+
+        assert!(!batch.all_ok());
+        assert_eq!(batch.succeeded(), &["iqn.a", "iqn.d"]);
+        assert_eq!(batch.failed().len(), 2);
+        assert!(matches!(batch.failed()[0].1, ScstError::GroupIniExists(_)));
+        assert!(matches!(batch.failed()[1].1, ScstError::Unknown(_)));
+
+        let failed = batch.into_result().unwrap_err();
+        assert_eq!(failed[0].0, "iqn.b");
+        assert_eq!(failed[1].0, "iqn.c");
+
+        let mut batch = BatchResult::new();
+        batch.push(0, Ok(()));
+        assert!(batch.all_ok());
+        assert_eq!(batch.into_result().unwrap(), vec![0]);
+    }
+}
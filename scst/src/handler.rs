@@ -1,12 +1,114 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
+use std::fs;
 use std::path::Path;
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::device::Device;
-use crate::{Layer, Options, ScstError, cmd_with_options, read_dir, read_fl};
+use crate::device::{Device, check_dif};
+use crate::{
+    Layer, Options, ScstError, cmd_with_options, load_created, read_dir, read_extra, read_fl,
+};
+
+// the handler attributes captured by typed fields
+static HANDLER_ATTRS: &[&str] = &["type"];
+// the parameters of `add_device`, common to the vdisk handlers
+pub(crate) static DEVICE_CREATE_PARAMS: &[&str] = &[
+    "active",
+    "bind_alua_state",
+    "blocksize",
+    "cluster_mode",
+    "dif_filename",
+    "dif_mode",
+    "dif_static_app_tag",
+    "dif_type",
+    "filename",
+    "numa_node_id",
+    "nv_cache",
+    "read_only",
+    "removable",
+    "rotational",
+    "thin_provisioned",
+    "tst",
+    "write_through",
+];
+// SCST_MAX_NAME of the kernel, including the trailing nul
+const DEVICE_NAME_MAX_LEN: usize = 49;
+
+/// checks the device name is a valid SCST identifier, `ScstError::InvalidName` returns if it's
+/// empty, too long or has characters other than alphanumerics, `_`, `-` and `.`.
+pub fn validate_device_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= DEVICE_NAME_MAX_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if !valid {
+        anyhow::bail!(ScstError::InvalidName(name.to_string()))
+    }
+
+    Ok(())
+}
+
+/// the kind of a handler, told by the handler name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HandlerType {
+    /// `vdisk_blockio`
+    Blockio,
+    /// `vdisk_fileio`
+    Fileio,
+    /// `vdisk_nullio`
+    Nullio,
+    /// `vcdrom`
+    Cdrom,
+    /// `dev_changer`
+    Changer,
+    /// `dev_processor`
+    Processor,
+    /// the other pass-through handlers, like `dev_disk` or `dev_tape`
+    Dev,
+    Other(String),
+}
+
+impl HandlerType {
+    pub fn from_name<S: AsRef<str>>(name: S) -> Self {
+        match name.as_ref() {
+            "vdisk_blockio" => HandlerType::Blockio,
+            "vdisk_fileio" => HandlerType::Fileio,
+            "vdisk_nullio" => HandlerType::Nullio,
+            "vcdrom" => HandlerType::Cdrom,
+            "dev_changer" => HandlerType::Changer,
+            "dev_processor" => HandlerType::Processor,
+            name if name.starts_with("dev_") => HandlerType::Dev,
+            name => HandlerType::Other(name.to_string()),
+        }
+    }
+
+    /// the handler creates virtual devices, instead of passing through SCSI devices.
+    pub fn is_virtual(&self) -> bool {
+        matches!(
+            self,
+            HandlerType::Blockio | HandlerType::Fileio | HandlerType::Nullio | HandlerType::Cdrom
+        )
+    }
+
+    /// `add_device` of the handler takes the backing `filename`. The pass-through handlers
+    /// take a SCSI address, and `vdisk_nullio` and `vcdrom` have no backing file on creation.
+    /// The unknown handlers are assumed to take it.
+    pub fn accepts_filename(&self) -> bool {
+        matches!(
+            self,
+            HandlerType::Blockio | HandlerType::Fileio | HandlerType::Other(_)
+        )
+    }
+}
+
+impl Default for HandlerType {
+    fn default() -> Self {
+        HandlerType::Other(String::new())
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Handler {
@@ -14,6 +116,10 @@ pub struct Handler {
     root: String,
     name: String,
     r#type: String,
+    #[serde(skip)]
+    handler_type: HandlerType,
+    #[serde(default)]
+    extra: BTreeMap<String, String>,
 
     devices: BTreeMap<String, Device>,
 }
@@ -27,6 +133,20 @@ impl Handler {
         &self.r#type
     }
 
+    pub fn handler_type(&self) -> &HandlerType {
+        &self.handler_type
+    }
+
+    /// the raw usage text of the handler `mgmt`, like the parameters of `add_device`.
+    pub fn mgmt_help(&self) -> Result<String> {
+        Layer::mgmt_help(self)
+    }
+
+    /// the readable attributes without typed support, like `trace_level`.
+    pub fn extra(&self) -> &BTreeMap<String, String> {
+        &self.extra
+    }
+
     pub fn devices(&self) -> Vec<&Device> {
         self.devices.values().collect()
     }
@@ -37,7 +157,14 @@ impl Handler {
             .context(ScstError::NoDevice(name.as_ref().to_string()))
     }
 
-    /// add a device for handler.
+    pub fn get_device_mut<S: AsRef<str>>(&mut self, name: S) -> Result<&mut Device> {
+        self.devices
+            .get_mut(name.as_ref())
+            .context(ScstError::NoDevice(name.as_ref().to_string()))
+    }
+
+    /// add a device for handler, returns the new device. `ScstError::HandlerBadAttr` returns if
+    /// the handler doesn't take a filename, see `HandlerType::accepts_filename()`.
     ///
     /// ```no_run
     /// use scst::{Scst, Options};
@@ -55,33 +182,100 @@ impl Handler {
         name: S,
         filename: S,
         options: &Options,
-    ) -> Result<()> {
-        let name_ref = name.as_ref();
+    ) -> Result<&mut Device> {
+        self.add_device_with(name.as_ref(), filename.as_ref(), options, &[])
+    }
+
+    /// add a `vdisk_fileio` device backed by a file of `size_bytes`. A missing file is created
+    /// sparse, but an existing file of another size is refused rather than truncated.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Options, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let device = scst.get_handler_mut("vdisk_fileio")?.add_fileio_device(
+    ///         "img1",
+    ///         "/var/lib/scst/img1.img",
+    ///         10 << 30,
+    ///         &Options::new(),
+    ///     )?;
+    ///     println!("{}", device.size());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn add_fileio_device<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        filename: S,
+        size_bytes: u64,
+        options: &Options,
+    ) -> Result<&mut Device> {
+        let (name_ref, filename_ref) = (name.as_ref(), filename.as_ref());
+        let created = match fs::metadata(filename_ref) {
+            Ok(meta) if meta.len() != size_bytes => {
+                anyhow::bail!(ScstError::DeviceFileSize {
+                    filename: filename_ref.to_string(),
+                    size: meta.len(),
+                })
+            }
+            Ok(_) => false,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // create_new doesn't truncate a file appeared in the meantime
+                let file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(filename_ref)
+                    .map_err(ScstError::Io)?;
+                if let Err(e) = file.set_len(size_bytes) {
+                    fs::remove_file(filename_ref).ok();
+                    return Err(ScstError::Io(e).into());
+                }
+                true
+            }
+            Err(e) => return Err(ScstError::Io(e).into()),
+        };
+
+        let mut options = options.clone();
+        let size = size_bytes.to_string();
+        options.insert("size", size.as_str());
+        match self.add_device_with(name_ref, filename_ref, &options, &["size"]) {
+            Ok(device) => Ok(device),
+            Err(e) => {
+                if created {
+                    fs::remove_file(filename_ref).ok();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn add_device_with(
+        &mut self,
+        name_ref: &str,
+        filename: &str,
+        options: &Options,
+        extra_params: &[&str],
+    ) -> Result<&mut Device> {
+        validate_device_name(name_ref)?;
+        if !self.handler_type.accepts_filename() {
+            return Err(anyhow::Error::new(ScstError::HandlerBadAttr)
+                .context(format!("handler '{}' doesn't take a filename", self.name)));
+        }
         if self.devices.contains_key(name_ref) {
             anyhow::bail!(ScstError::DeviceExists(name_ref.to_string()))
         }
+        check_dif(options)?;
 
         let root = self.root().to_path_buf();
-        let mut cmd = format!("add_device {} filename={}", name_ref, filename.as_ref());
-        let params = vec![
-            "active".to_string(),
-            "bind_alua_state".to_string(),
-            "blocksize".to_string(),
-            "cluster_mode".to_string(),
-            "dif_filename".to_string(),
-            "dif_mode".to_string(),
-            "dif_static_app_tag".to_string(),
-            "dif_type".to_string(),
-            "filename".to_string(),
-            "numa_node_id".to_string(),
-            "nv_cache".to_string(),
-            "read_only".to_string(),
-            "removable".to_string(),
-            "rotational".to_string(),
-            "thin_provisioned".to_string(),
-            "tst".to_string(),
-            "write_through".to_string(),
-        ];
+        let mut cmd = format!("add_device {} filename={}", name_ref, filename);
+        let params = DEVICE_CREATE_PARAMS
+            .iter()
+            .chain(extra_params)
+            .map(|p| p.to_string())
+            .collect::<Vec<String>>();
 
         cmd = cmd_with_options(&cmd, &params, &options)?;
 
@@ -92,10 +286,12 @@ impl Handler {
             })?;
 
         let mut device = Device::default();
-        device.load(self.root().join(name_ref))?;
-        self.devices.insert(device.name().to_string(), device);
+        load_created(&mut device, self.root().join(name_ref))?;
 
-        Ok(())
+        Ok(self
+            .devices
+            .entry(device.name().to_string())
+            .or_insert(device))
     }
 
     /// delete device for handler
@@ -135,8 +331,10 @@ impl Layer for Handler {
             .unwrap_or(OsStr::new(""))
             .to_string_lossy()
             .to_string();
+        self.handler_type = HandlerType::from_name(&self.name);
         self.root = root_ref.to_string_lossy().to_string();
         self.r#type = read_fl(root_ref.join("type"))?;
+        self.extra = read_extra(root_ref, HANDLER_ATTRS);
 
         // traverse device directory
         self.devices = read_dir(root_ref)?
@@ -152,3 +350,260 @@ impl Layer for Handler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{Fixture, echoed_cmds};
+    use crate::{DifMode, DifType};
+
+    #[test]
+    fn load_extra() -> Result<()> {
+        let fx = Fixture::new("handler_load_extra");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/trace_level", "out_of_mem | minor\n")
+            .file("handlers/vdisk_blockio/mgmt", "Usage: ...\n");
+
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_blockio"))?;
+
+        let extra = handler.extra();
+        assert_eq!(
+            extra.get("trace_level").map(|v| v.as_str()),
+            Some("out_of_mem | minor")
+        );
+        assert!(!extra.contains_key("type"));
+        assert!(!extra.contains_key("mgmt"));
+        assert!(!extra.contains_key("vol"));
+        assert!(handler.get_device("vol").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn handler_type_from_name() {
+        assert_eq!(
+            HandlerType::from_name("vdisk_blockio"),
+            HandlerType::Blockio
+        );
+        assert_eq!(HandlerType::from_name("vdisk_fileio"), HandlerType::Fileio);
+        assert_eq!(HandlerType::from_name("dev_changer"), HandlerType::Changer);
+        assert_eq!(HandlerType::from_name("dev_disk"), HandlerType::Dev);
+        assert_eq!(
+            HandlerType::from_name("vdisk_foo"),
+            HandlerType::Other("vdisk_foo".to_string())
+        );
+        assert!(HandlerType::Nullio.is_virtual());
+        assert!(!HandlerType::Dev.is_virtual());
+    }
+
+    #[test]
+    fn get_device_mut() -> Result<()> {
+        let fx = Fixture::new("handler_get_device_mut");
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol");
+
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_blockio"))?;
+
+        handler
+            .get_device_mut("vol")?
+            .set_attribute("read_only", "1")?;
+        assert!(handler.get_device("vol")?.read_only());
+
+        let err = handler.get_device_mut("missing").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::NoDevice(n)) if n == "missing"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_device_name() -> Result<()> {
+        validate_device_name("vol-1_a.img")?;
+        validate_device_name(&"v".repeat(DEVICE_NAME_MAX_LEN))?;
+
+        let long = "v".repeat(DEVICE_NAME_MAX_LEN + 1);
+        for name in &["", "tank/vol", "my vol", "vol;rm", "völ", long.as_str()] {
+            let err = validate_device_name(name).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ScstError>(),
+                Some(ScstError::InvalidName(n)) if n == name
+            ));
+        }
+
+        let fx = Fixture::new("add_device_invalid_name");
+        fx.handler("vdisk_blockio", "0 - virtual\n");
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_blockio"))?;
+        assert!(
+            handler
+                .add_device("tank/vol", "/dev/zvol/tank/vol", &Options::new())
+                .is_err()
+        );
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_fileio_device() -> Result<()> {
+        let fx = Fixture::new("add_fileio_device");
+        fx.handler("vdisk_fileio", "0 - virtual\n").simulate();
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_fileio"))?;
+
+        let img = fx.path("img1.img");
+        let filename = img.to_string_lossy().to_string();
+        let device =
+            handler.add_fileio_device("img1", filename.as_str(), 1 << 20, &Options::new())?;
+        assert_eq!(device.name(), "img1");
+        assert_eq!(device.filename(), img.as_path());
+        assert_eq!(fs::metadata(&img)?.len(), 1 << 20);
+        assert_eq!(
+            echoed_cmds(),
+            vec![format!(
+                "add_device img1 filename={} size=1048576",
+                filename
+            )]
+        );
+
+        // an existing file of the same size is reused, another size is refused
+        handler.del_device("img1")?;
+        handler.add_fileio_device("img1", filename.as_str(), 1 << 20, &Options::new())?;
+        handler.del_device("img1")?;
+        echoed_cmds();
+        let err = handler
+            .add_fileio_device("img1", filename.as_str(), 2 << 20, &Options::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceFileSize { size, .. }) if *size == 1 << 20
+        ));
+        assert_eq!(fs::metadata(&img)?.len(), 1 << 20);
+        assert!(echoed_cmds().is_empty());
+
+        // a refused device doesn't leave the file it created behind
+        handler.add_fileio_device("img1", filename.as_str(), 1 << 20, &Options::new())?;
+        let img2 = fx.path("img2.img");
+        let err = handler
+            .add_fileio_device(
+                "img1",
+                img2.to_string_lossy().as_ref(),
+                1 << 20,
+                &Options::new(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceExists(_))
+        ));
+        assert!(!img2.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_device_dif() -> Result<()> {
+        let fx = Fixture::new("add_device_dif");
+        fx.handler("vdisk_fileio", "0 - virtual\n").simulate();
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_fileio"))?;
+
+        let mut options = Options::new();
+        options.insert_dif(&[DifMode::Tgt, DifMode::DevStore], DifType::Type1);
+        options.insert("dif_filename", "/var/lib/scst/img1.dif");
+        handler.add_device("img1", "/var/lib/scst/img1.img", &options)?;
+        assert_eq!(
+            echoed_cmds(),
+            vec![
+                "add_device img1 filename=/var/lib/scst/img1.img \
+                 dif_filename=/var/lib/scst/img1.dif;dif_mode=tgt|dev_store;dif_type=1"
+            ]
+        );
+
+        // dev_store keeps the protection information in dif_filename
+        let mut options = Options::new();
+        options.insert_dif(&[DifMode::DevStore], DifType::Type3);
+        let err = handler
+            .add_device("img2", "/var/lib/scst/img2.img", &options)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceBadDif(_))
+        ));
+
+        let mut options = Options::new();
+        options.insert_dif(&[DifMode::Tgt], DifType::Type0);
+        assert!(
+            handler
+                .add_device("img2", "/var/lib/scst/img2.img", &options)
+                .is_err()
+        );
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_device_returns_device() -> Result<()> {
+        let fx = Fixture::new("add_device_returns_device");
+        fx.handler("vdisk_blockio", "0 - virtual\n").simulate();
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_blockio"))?;
+
+        let device = handler.add_device("vol", "/dev/zvol/tank/vol", &Options::new())?;
+        assert_eq!(device.name(), "vol");
+        device.set_attribute("read_only", "1")?;
+        assert!(handler.get_device("vol")?.read_only());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_device_filename_handler_type() -> Result<()> {
+        let fx = Fixture::new("add_device_filename_handler_type");
+        fx.handler("vdisk_nullio", "0 - virtual\n");
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_nullio"))?;
+        assert!(!handler.handler_type().accepts_filename());
+
+        let err = handler
+            .add_device("null1", "/dev/null", &Options::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::HandlerBadAttr)
+        ));
+        assert!(echoed_cmds().is_empty());
+
+        assert!(HandlerType::Blockio.accepts_filename());
+        assert!(!HandlerType::Dev.accepts_filename());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mgmt_help() -> Result<()> {
+        let fx = Fixture::new("handler_mgmt_help");
+        let usage = "Usage: echo \"add_device device_name [parameters]\" >mgmt\n\
+                     The following parameters available: filename, read_only\n";
+        fx.handler("vdisk_fileio", "0 - virtual\n")
+            .file("handlers/vdisk_fileio/mgmt", usage);
+
+        let mut handler = Handler::default();
+        handler.load(fx.path("handlers/vdisk_fileio"))?;
+        let text = handler.mgmt_help()?;
+        assert_eq!(text, usage);
+        assert_eq!(
+            crate::parse_mgmt_help(&text).parameters(),
+            &["filename", "read_only"]
+        );
+
+        fx.remove("handlers/vdisk_fileio/mgmt");
+        assert!(handler.mgmt_help().is_err());
+
+        Ok(())
+    }
+}
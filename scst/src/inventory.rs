@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Device, Lun, Scst, Target};
+
+/// a LUN exported by a target or an initiator group, with its resolved device.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct InventoryRow {
+    driver: String,
+    target: String,
+    group: Option<String>,
+    lun: u64,
+    device: String,
+    handler: Option<String>,
+    filename: Option<String>,
+    device_active: Option<bool>,
+}
+
+impl InventoryRow {
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// the initiator group of the LUN, None for a target level LUN.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub fn lun(&self) -> u64 {
+        self.lun
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// the handler of the device, None if the device isn't found.
+    pub fn handler(&self) -> Option<&str> {
+        self.handler.as_deref()
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// the active state of the device, None if the device isn't found.
+    pub fn device_active(&self) -> Option<bool> {
+        self.device_active
+    }
+
+    /// the LUN still exists but serves errors, since its device is offline or missing.
+    pub fn is_offline(&self) -> bool {
+        self.device_active != Some(true)
+    }
+}
+
+impl Scst {
+    /// lists every LUN of the targets and initiator groups of every driver with its resolved
+    /// device, so the LUNs backed by offline devices can be spotted.
+    pub fn inventory(&self) -> Vec<InventoryRow> {
+        let mut rows = Vec::new();
+
+        for driver in self.drivers() {
+            for target in driver.targets() {
+                for lun in target.luns() {
+                    rows.push(self.inventory_row(driver.name(), target, None, lun));
+                }
+                for group in target.ini_groups() {
+                    for lun in group.luns() {
+                        rows.push(self.inventory_row(
+                            driver.name(),
+                            target,
+                            Some(group.name()),
+                            lun,
+                        ));
+                    }
+                }
+            }
+        }
+
+        rows
+    }
+
+    /// the targets with a LUN mapping the device at the target or group level, with the names
+    /// of their drivers.
+    pub fn targets_exposing(&self, device: &str) -> Vec<(&str, &Target)> {
+        self.drivers()
+            .into_iter()
+            .flat_map(|driver| {
+                driver
+                    .targets()
+                    .into_iter()
+                    .map(move |target| (driver.name(), target))
+            })
+            .filter(|(_, target)| {
+                target.luns().iter().any(|lun| lun.device() == device)
+                    || target
+                        .ini_groups()
+                        .iter()
+                        .any(|group| group.luns().iter().any(|lun| lun.device() == device))
+            })
+            .collect()
+    }
+
+    fn inventory_row(
+        &self,
+        driver: &str,
+        target: &Target,
+        group: Option<&str>,
+        lun: &Lun,
+    ) -> InventoryRow {
+        let device = self.find_device(lun.device());
+
+        InventoryRow {
+            driver: driver.to_string(),
+            target: target.name().to_string(),
+            group: group.map(|g| g.to_string()),
+            lun: lun.id(),
+            device: lun.device().to_string(),
+            handler: device.map(|d| d.handler().to_string()),
+            filename: device.map(|d| d.filename().to_string_lossy().to_string()),
+            device_active: device.map(|d| d.is_active()),
+        }
+    }
+
+    pub(crate) fn find_device(&self, name: &str) -> Option<&Device> {
+        self.handlers()
+            .into_iter()
+            .find_map(|h| h.get_device(name).ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use crate::Scst;
+    use crate::testutil::Fixture;
+
+    #[test]
+    fn inventory_offline_device() -> Result<()> {
+        let fx = Fixture::new("inventory_offline_device");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .file("handlers/vdisk_blockio/vol2/active", "0\n")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .group(tgt, "grp", &[])
+            .lun(&format!("{}/ini_groups/grp", tgt), 0, "vol2")
+            .lun(&format!("{}/ini_groups/grp", tgt), 1, "missing");
+        let scst = Scst::init_at(fx.root())?;
+
+        let rows = scst.inventory();
+        assert_eq!(rows.len(), 3);
+
+        assert_eq!(rows[0].group(), None);
+        assert_eq!(rows[0].device(), "vol1");
+        assert_eq!(rows[0].handler(), Some("vdisk_blockio"));
+        assert_eq!(rows[0].device_active(), Some(true));
+        assert!(!rows[0].is_offline());
+
+        assert_eq!(rows[1].group(), Some("grp"));
+        assert_eq!(rows[1].device(), "vol2");
+        assert_eq!(rows[1].device_active(), Some(false));
+        assert!(rows[1].is_offline());
+
+        assert_eq!(rows[2].device(), "missing");
+        assert_eq!(rows[2].device_active(), None);
+        assert!(rows[2].is_offline());
+
+        Ok(())
+    }
+
+    #[test]
+    fn targets_exposing() -> Result<()> {
+        let fx = Fixture::new("targets_exposing");
+        let tgt1 = "targets/iscsi/iqn.2018-11.com.vine:vol1";
+        let tgt2 = "targets/iscsi/iqn.2018-11.com.vine:vol2";
+        let fc = "targets/qla2x00t/21:00:00:24:ff:01:02:03";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol3")
+            .lun(tgt1, 0, "vol1")
+            .group(tgt2, "grp", &[])
+            .lun(&format!("{}/ini_groups/grp", tgt2), 3, "vol1")
+            .lun(tgt2, 0, "vol2")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03")
+            .lun(fc, 0, "vol1");
+        let scst = Scst::init_at(fx.root())?;
+
+        let targets = scst
+            .targets_exposing("vol1")
+            .into_iter()
+            .map(|(driver, target)| (driver, target.name()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            targets,
+            vec![
+                ("iscsi", "iqn.2018-11.com.vine:vol1"),
+                ("iscsi", "iqn.2018-11.com.vine:vol2"),
+                ("qla2x00t", "21:00:00:24:ff:01:02:03"),
+            ]
+        );
+        assert!(scst.targets_exposing("missing").is_empty());
+
+        Ok(())
+    }
+}
@@ -1,34 +1,58 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
 use std::io::Write;
+use std::iter::FromIterator;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use anyhow::Result;
 
 mod config;
 mod copy_manager;
 mod device;
+mod device_group;
 mod error;
 mod handler;
+mod inventory;
+#[cfg(feature = "locking")]
+mod lock;
+mod mgmt;
 mod scst_tgt;
 mod stat;
 mod target;
+#[cfg(test)]
+mod testutil;
 
 pub use config::*;
 pub use copy_manager::*;
 pub use device::*;
+pub use device_group::*;
 pub use error::*;
 pub use handler::*;
+pub use inventory::*;
+pub use mgmt::*;
 pub use scst_tgt::*;
 pub use stat::*;
 pub use target::*;
 
+// the attempts to load a just created object, see `load_created()`
+const LOAD_RETRIES: usize = 3;
+static LOAD_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
 pub(crate) trait Layer {
     fn root(&self) -> &Path;
 
     fn load<P: AsRef<Path>>(&mut self, root: P) -> Result<()>;
 
+    /// reads the usage text of the `mgmt` file, it's parsed by `parse_mgmt_help()`.
+    fn mgmt_help(&self) -> Result<String> {
+        read_mgmt_help(self.root())
+    }
+
     fn mgmt<S: AsRef<OsStr>>(&mut self, root: S, cmd: S) -> Result<()> {
         let mgmt = Path::new(root.as_ref()).join("mgmt");
         // println!(
@@ -36,19 +60,26 @@ pub(crate) trait Layer {
         //     cmd.as_ref().to_string_lossy(),
         //     mgmt.to_string_lossy()
         // );
-        echo(mgmt.as_ref(), cmd.as_ref())
+        echo(mgmt.as_ref(), cmd.as_ref()).map_err(|e| match raw_os_error(&e) {
+            Some(errno) => {
+                let cmd = cmd.as_ref().to_string_lossy();
+                map_errno(errno, &MgmtContext::new(&mgmt, &cmd)).into()
+            }
+            None => e,
+        })
     }
 }
 
-#[derive(Debug, Default, Clone)]
+/// the parameters of a mgmt command, kept ordered by key so the packed string is stable.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct Options {
-    inner: HashMap<String, String>,
+    inner: BTreeMap<String, String>,
 }
 
 impl Options {
     pub fn new() -> Self {
         Options {
-            inner: HashMap::new(),
+            inner: BTreeMap::new(),
         }
     }
 
@@ -58,6 +89,58 @@ impl Options {
         self
     }
 
+    /// inserts a boolean attribute as `1` or `0`, like `read_only`.
+    pub fn insert_bool<S: AsRef<str>>(&mut self, k: S, v: bool) -> &Self {
+        self.insert(k.as_ref(), if v { "1" } else { "0" })
+    }
+
+    /// inserts an integer attribute, like `blocksize`.
+    pub fn insert_int<S: AsRef<str>, N: Into<i64>>(&mut self, k: S, v: N) -> &Self {
+        self.insert(k.as_ref(), v.into().to_string().as_str())
+    }
+
+    /// inserts the DIF attributes `dif_mode` and `dif_type` of a device to create, the
+    /// `dif_filename` of `DifMode::DevStore` is inserted as usual.
+    pub fn insert_dif(&mut self, modes: &[DifMode], dif_type: DifType) -> &Self {
+        let mode = modes
+            .iter()
+            .map(|mode| mode.as_str())
+            .collect::<Vec<&str>>()
+            .join("|");
+        self.insert("dif_mode", mode.as_str());
+        self.insert_int("dif_type", dif_type.value())
+    }
+
+    pub fn get(&self, k: &str) -> Option<&str> {
+        self.inner.get(k).map(|v| v.as_str())
+    }
+
+    /// removes the option, returns its value if it was set.
+    pub fn remove(&mut self, k: &str) -> Option<String> {
+        self.inner.remove(k)
+    }
+
+    /// layers the options of `other` on top, its values win on conflict.
+    ///
+    /// ```
+    /// use scst::Options;
+    ///
+    /// let mut base = Options::new();
+    /// base.insert("blocksize", "4096");
+    /// base.insert("read_only", "0");
+    ///
+    /// let mut overrides = Options::new();
+    /// overrides.insert("read_only", "1");
+    ///
+    /// base.merge(&overrides);
+    /// assert_eq!(base.pack(), Some("blocksize=4096;read_only=1".to_string()));
+    /// ```
+    pub fn merge(&mut self, other: &Options) {
+        for (k, v) in &other.inner {
+            self.inner.insert(k.clone(), v.clone());
+        }
+    }
+
     pub fn contains_keys<'a>(&self, keys: &'a [String]) -> Vec<&'a str> {
         keys.iter()
             .filter(|key| self.inner.contains_key(*key))
@@ -104,6 +187,25 @@ impl Options {
         None
     }
 
+    /// parses the string packed by `pack()` back to Options. The fragments without `=` are
+    /// skipped.
+    ///
+    /// ```
+    /// use scst::Options;
+    ///
+    /// let opt = Options::unpack("blocksize=4096;read_only=1");
+    /// assert_eq!(opt.pack(), Some("blocksize=4096;read_only=1".to_string()));
+    /// assert_eq!(Options::unpack(""), Options::new());
+    /// ```
+    pub fn unpack(s: &str) -> Options {
+        let mut opt = Options::new();
+        for (key, value) in s.split(';').filter_map(|pair| pair.split_once('=')) {
+            opt.insert(key.trim(), value.trim());
+        }
+
+        opt
+    }
+
     /// like `pack()`, but checks input firstly.
     ///
     /// ```no_run
@@ -134,6 +236,55 @@ impl Options {
 
         Ok(self.pack())
     }
+
+    /// the former name of `check_pack()`.
+    #[deprecated(note = "use `check_pack()` instead")]
+    pub fn pack_with_check(&self, keys: &[String]) -> Result<Option<String>> {
+        self.check_pack(keys)
+    }
+}
+
+/// formats as the packed `k=v;...` string written to mgmt, empty if there are no options.
+impl fmt::Display for Options {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pack().unwrap_or_default())
+    }
+}
+
+impl FromIterator<(String, String)> for Options {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Options {
+            inner: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// iterates the options ordered by key.
+impl IntoIterator for Options {
+    type Item = (String, String);
+    type IntoIter = std::collections::btree_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Options {
+    type Item = (&'a String, &'a String);
+    type IntoIter = std::collections::btree_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter()
+    }
+}
+
+/// parses an IPv4 or IPv6 address, the IPv6 address may be enclosed in brackets.
+pub(crate) fn parse_ip(s: &str) -> Option<IpAddr> {
+    s.strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s)
+        .parse::<IpAddr>()
+        .ok()
 }
 
 pub(crate) fn read_fl<P: AsRef<Path>>(path: P) -> Result<String> {
@@ -143,6 +294,61 @@ pub(crate) fn read_fl<P: AsRef<Path>>(path: P) -> Result<String> {
     Ok(value)
 }
 
+/// reads a boolean attribute like `active`, `enabled` or `read_only`. Values other than 0 and 1
+/// are refused with `ScstError::BadBool` rather than read as false.
+pub(crate) fn read_bool<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let value = read_fl(path.as_ref())?;
+    parse_bool(path, value)
+}
+
+pub(crate) fn parse_bool<P: AsRef<Path>>(path: P, value: String) -> Result<bool> {
+    match value.as_str() {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => anyhow::bail!(ScstError::BadBool {
+            attr: path.as_ref().to_string_lossy().to_string(),
+            value,
+        }),
+    }
+}
+
+/// reads the usage text of the `mgmt` file under `dir`.
+pub(crate) fn read_mgmt_help<P: AsRef<Path>>(dir: P) -> Result<String> {
+    let text = fs::read_to_string(dir.as_ref().join("mgmt")).map_err(ScstError::Io)?;
+
+    Ok(text)
+}
+
+/// reads the attribute files under `path` not captured by typed fields in `known`. The
+/// subdirectories, symlinks, `mgmt` and unreadable files are skipped.
+pub(crate) fn read_extra<P: AsRef<Path>>(path: P, known: &[&str]) -> BTreeMap<String, String> {
+    let entries = match read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return BTreeMap::new(),
+    };
+
+    entries
+        .filter_map(|res| res.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == "mgmt" || known.contains(&name.as_str()) {
+                return None;
+            }
+            read_fl(entry.path()).ok().map(|value| (name, value))
+        })
+        .collect()
+}
+
+/// a sysfs attribute file without any write permission, which can't be set.
+pub(crate) fn is_static_attr<P: AsRef<Path>>(path: P) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|m| m.permissions().mode() & 0o222 == 0)
+        .unwrap_or(false)
+}
+
 pub(crate) fn read_dir<P: AsRef<Path>>(path: P) -> Result<fs::ReadDir> {
     let read_dir = fs::read_dir(path).map_err(|e| ScstError::Io(e))?;
     Ok(read_dir)
@@ -155,12 +361,47 @@ pub(crate) fn read_link<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
 
 pub(crate) fn echo<S: AsRef<OsStr>>(root: S, cmd: S) -> Result<()> {
     let cmd_str = cmd.as_ref().to_string_lossy();
+    #[cfg(test)]
+    testutil::record(Path::new(root.as_ref()), &cmd_str);
     let mut fd = fs::File::create(Path::new(root.as_ref()))?;
     fd.write(cmd_str.as_bytes()).map_err(|e| ScstError::Io(e))?;
 
     Ok(())
 }
 
+/// loads the object just created by a `mgmt` command. The kernel may add its attribute files a
+/// bit after the directory, so the load is retried a few times before giving up.
+pub(crate) fn load_created<L: Layer, P: AsRef<Path>>(layer: &mut L, root: P) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match layer.load(root.as_ref()) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= LOAD_RETRIES => return Err(e),
+            Err(_) => {
+                attempt += 1;
+                thread::sleep(LOAD_RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+/// the errno of a failed `echo`, if it's a failure of the system call.
+/// wraps `e` in the generic failure `fail`, unless it's already the specific cause given by
+/// `map_errno()`, like `ScstError::GroupIniExists`, which stays the outermost error then.
+pub(crate) fn fail_context(e: anyhow::Error, fail: ScstError) -> anyhow::Error {
+    match e.downcast_ref::<ScstError>() {
+        Some(ScstError::Io(_)) | None => e.context(fail),
+        Some(_) => e,
+    }
+}
+
+fn raw_os_error(e: &anyhow::Error) -> Option<i32> {
+    match e.downcast_ref::<ScstError>() {
+        Some(ScstError::Io(io)) => io.raw_os_error(),
+        _ => e.downcast_ref::<std::io::Error>()?.raw_os_error(),
+    }
+}
+
 pub(crate) fn cmd_with_options(
     cmd: &str,
     params: &Vec<String>,
@@ -208,6 +449,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_bool() -> Result<()> {
+        let fx = testutil::Fixture::new("read_bool");
+        fx.file("off", "0\n").file("on", "1\n").file("bad", "2\n");
+
+        assert!(!read_bool(fx.path("off"))?);
+        assert!(read_bool(fx.path("on"))?);
+        let err = read_bool(fx.path("bad")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::BadBool { value, .. }) if value == "2"
+        ));
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Created {
+        root: PathBuf,
+        attempts: usize,
+    }
+
+    impl Layer for Created {
+        fn root(&self) -> &Path {
+            &self.root
+        }
+
+        fn load<P: AsRef<Path>>(&mut self, root: P) -> Result<()> {
+            self.root = root.as_ref().to_path_buf();
+            self.attempts += 1;
+            let res = read_fl(self.root.join("tid"));
+            // the kernel finishes the attribute files after the first load attempt
+            fs::write(self.root.join("tid"), "1\n")?;
+            res.map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_load_created() -> Result<()> {
+        let fx = testutil::Fixture::new("load_created");
+        fx.dir("tgt");
+
+        let mut created = Created::default();
+        load_created(&mut created, fx.path("tgt"))?;
+        assert_eq!(created.attempts, 2);
+
+        let mut missing = Created::default();
+        assert!(load_created(&mut missing, fx.path("missing")).is_err());
+        assert_eq!(missing.attempts, LOAD_RETRIES);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_ip() {
+        assert_eq!(
+            parse_ip("192.168.2.30"),
+            Some("192.168.2.30".parse().unwrap())
+        );
+        assert_eq!(parse_ip("fe80::1"), Some("fe80::1".parse().unwrap()));
+        assert_eq!(parse_ip("[fe80::1]"), Some("fe80::1".parse().unwrap()));
+        assert_eq!(
+            parse_ip("fe80:0000:0000:0000:0000:0000:0000:0001"),
+            Some("fe80::1".parse().unwrap())
+        );
+        assert_eq!(parse_ip("192.168.2.300"), None);
+        assert_eq!(parse_ip("192.168.2"), None);
+        assert_eq!(parse_ip("[192.168.2.30"), None);
+        assert_eq!(parse_ip("fe80::1::2"), None);
+        assert_eq!(parse_ip("iqn.2018-11.com.vine"), None);
+    }
+
     #[test]
     pub fn test_options() -> Result<()> {
         let mut opt = Options::new();
@@ -225,4 +538,146 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_options_pack_with_check() -> Result<()> {
+        let mut opt = Options::new();
+        opt.insert("read_only", "1");
+
+        let keys = ["read_only".to_string()];
+        assert_eq!(opt.pack_with_check(&keys)?, opt.check_pack(&keys)?);
+        assert!(opt.pack_with_check(&[]).is_err());
+        assert!(opt.check_pack(&[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_pack_ordered() {
+        let mut a = Options::new();
+        a.insert("rotational", "0");
+        a.insert("blocksize", "4096");
+        a.insert("read_only", "1");
+
+        let mut b = Options::new();
+        b.insert("read_only", "1");
+        b.insert("rotational", "0");
+        b.insert("blocksize", "4096");
+
+        let packed = Some("blocksize=4096;read_only=1;rotational=0".to_string());
+        assert_eq!(a.pack(), packed);
+        assert_eq!(b.pack(), packed);
+    }
+
+    #[test]
+    fn test_options_typed_insert() {
+        let mut typed = Options::new();
+        typed.insert_bool("read_only", true);
+        typed.insert_bool("thin_provisioned", false);
+        typed.insert_int("blocksize", 4096);
+        typed.insert_int("threads_num", 4u8);
+
+        let mut text = Options::new();
+        text.insert("read_only", "1");
+        text.insert("thin_provisioned", "0");
+        text.insert("blocksize", "4096");
+        text.insert("threads_num", "4");
+
+        assert_eq!(typed, text);
+        assert_eq!(typed.pack(), text.pack());
+    }
+
+    #[test]
+    fn test_options_get_remove_merge() {
+        let mut opt = Options::new();
+        opt.insert("blocksize", "4096");
+        opt.insert("read_only", "0");
+        assert_eq!(opt.get("blocksize"), Some("4096"));
+        assert_eq!(opt.get("nv_cache"), None);
+
+        assert_eq!(opt.remove("blocksize"), Some("4096".to_string()));
+        assert_eq!(opt.remove("blocksize"), None);
+        assert_eq!(opt.get("blocksize"), None);
+
+        let mut overrides = Options::new();
+        overrides.insert("read_only", "1");
+        overrides.insert("nv_cache", "1");
+        opt.merge(&overrides);
+        assert_eq!(opt.pack(), Some("nv_cache=1;read_only=1".to_string()));
+    }
+
+    #[test]
+    fn test_options_iter() {
+        let opt: Options = [("read_only", "1"), ("nv_cache", "1")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(opt.pack(), Some("nv_cache=1;read_only=1".to_string()));
+
+        let keys = (&opt)
+            .into_iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(keys, vec!["nv_cache", "read_only"]);
+
+        let pairs = opt.into_iter().collect::<Vec<(String, String)>>();
+        assert_eq!(pairs[1], ("read_only".to_string(), "1".to_string()));
+    }
+
+    #[test]
+    fn test_options_display() {
+        let mut opt = Options::new();
+        assert_eq!(opt.to_string(), "");
+
+        opt.insert("read_only", "1");
+        opt.insert("blocksize", "4096");
+        assert_eq!(format!("{}", opt), opt.pack().unwrap());
+        assert_eq!(opt.to_string(), "blocksize=4096;read_only=1");
+    }
+
+    #[test]
+    fn test_options_unpack() {
+        let mut opt = Options::new();
+        opt.insert("filename", "/dev/zvol/tank/vol");
+        opt.insert("read_only", "1");
+        opt.insert("blocksize", "4096");
+
+        assert_eq!(Options::unpack(&opt.pack().unwrap()), opt);
+        assert_eq!(Options::unpack("read_only=1;bogus;"), {
+            let mut o = Options::new();
+            o.insert("read_only", "1");
+            o
+        });
+        assert_eq!(Options::unpack(""), Options::new());
+    }
+
+    #[test]
+    fn test_options_eq() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |opt: &Options| {
+            let mut hasher = DefaultHasher::new();
+            opt.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut a = Options::new();
+        a.insert("read_only", "1");
+        a.insert("blocksize", "4096");
+        a.insert("rotational", "0");
+
+        let mut b = Options::new();
+        b.insert("rotational", "0");
+        b.insert("read_only", "1");
+        b.insert("blocksize", "4096");
+
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+
+        b.insert("read_only", "0");
+        assert_ne!(a, b);
+        assert_ne!(hash(&a), hash(&b));
+    }
 }
@@ -0,0 +1,63 @@
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// an exclusive advisory lock on a file, released when it drops.
+#[derive(Debug)]
+pub(crate) struct ScstLock {
+    // the lock lives as long as the file is open
+    _file: File,
+}
+
+impl ScstLock {
+    /// takes the lock without blocking, `ScstError::Locked` returns if it's held already.
+    pub(crate) fn acquire<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("open lock file '{}'", path.display()))?;
+
+        // flock locks are owned by the open file, so two opens conflict in a process too.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::WouldBlock {
+                anyhow::bail!(crate::ScstError::Locked(path.to_string_lossy().to_string()))
+            }
+            return Err(e).context(format!("lock file '{}'", path.display()));
+        }
+
+        Ok(ScstLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use crate::testutil::Fixture;
+    use crate::{Scst, ScstError};
+
+    #[test]
+    fn init_locked_conflict() -> Result<()> {
+        let fx = Fixture::new("init_locked_conflict");
+        fx.scst();
+        let lock_file = fx.root().join("scst.lock");
+
+        let scst = Scst::init_locked_at(fx.root(), &lock_file)?;
+        let err = Scst::init_locked_at(fx.root(), &lock_file).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::Locked(_))
+        ));
+
+        drop(scst);
+        Scst::init_locked_at(fx.root(), &lock_file)?;
+
+        Ok(())
+    }
+}
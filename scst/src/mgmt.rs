@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ScstError;
+
+static USAGE_PREFIX: &str = "Usage:";
+static PARAMS_PREFIX: &str = "The following parameters available:";
+static ATTRS_PREFIX: &str = "The following ";
+static ATTRS_SUFFIX: &str = " attributes available:";
+
+// the errnos reported by writing a `mgmt` file
+const ENOENT: i32 = 2;
+const EBUSY: i32 = 16;
+const EEXIST: i32 = 17;
+const EINVAL: i32 = 22;
+
+/// the usage text SCST reports by reading a `mgmt` file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct MgmtHelp {
+    usage: Vec<String>,
+    parameters: Vec<String>,
+    attributes: BTreeMap<String, Vec<String>>,
+}
+
+impl MgmtHelp {
+    /// the commands accepted by `mgmt`, like `add_device device_name [parameters]`.
+    pub fn usage(&self) -> &[String] {
+        &self.usage
+    }
+
+    /// the parameters accepted by the create command, like `filename` of `add_device`.
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /// the attributes available for the given kind, like `target` or `target driver`.
+    pub fn attributes<S: AsRef<str>>(&self, kind: S) -> &[String] {
+        self.attributes
+            .get(kind.as_ref())
+            .map(|attrs| attrs.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// all attribute kinds available.
+    pub fn attribute_kinds(&self) -> Vec<&str> {
+        self.attributes.keys().map(|kind| kind.as_str()).collect()
+    }
+}
+
+/// parses the text read from a SCST `mgmt` file.
+///
+/// ```
+/// use scst::parse_mgmt_help;
+///
+/// let help = parse_mgmt_help(
+///     "Usage: echo \"add_device device_name [parameters]\" >mgmt\n\
+///      The following parameters available: filename, read_only\n",
+/// );
+/// assert_eq!(help.usage(), &["add_device device_name [parameters]"]);
+/// assert_eq!(help.parameters(), &["filename", "read_only"]);
+/// ```
+pub fn parse_mgmt_help(text: &str) -> MgmtHelp {
+    let mut help = MgmtHelp::default();
+
+    let mut in_usage = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix(USAGE_PREFIX) {
+            in_usage = true;
+            help.usage.extend(echo_cmd(rest));
+        } else if in_usage && line.starts_with("echo") {
+            help.usage.extend(echo_cmd(line));
+        } else if let Some(rest) = line.strip_prefix(PARAMS_PREFIX) {
+            in_usage = false;
+            help.parameters = split_list(rest);
+        } else if let Some(rest) = line.strip_prefix(ATTRS_PREFIX) {
+            in_usage = false;
+            if let Some(pos) = rest.find(ATTRS_SUFFIX) {
+                let kind = rest[..pos].to_string();
+                let attrs = split_list(&rest[pos + ATTRS_SUFFIX.len()..]);
+                help.attributes.insert(kind, attrs);
+            }
+        } else {
+            in_usage = false;
+        }
+    }
+
+    help
+}
+
+/// extracts the quoted command of `echo "<cmd>" >mgmt`.
+fn echo_cmd(line: &str) -> Option<String> {
+    let start = line.find('"')?;
+    let end = line.rfind('"')?;
+    if end <= start {
+        return None;
+    }
+
+    Some(line[start + 1..end].trim().to_string())
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.trim()
+        .trim_end_matches('.')
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// the `mgmt` file written and the command written to it.
+pub(crate) struct MgmtContext<'a> {
+    mgmt: &'a Path,
+    cmd: &'a str,
+}
+
+impl<'a> MgmtContext<'a> {
+    pub(crate) fn new(mgmt: &'a Path, cmd: &'a str) -> Self {
+        MgmtContext { mgmt, cmd }
+    }
+
+    fn dir_name(&self, n: usize) -> &str {
+        self.mgmt
+            .ancestors()
+            .nth(n)
+            .and_then(|p| p.file_name())
+            .and_then(OsStr::to_str)
+            .unwrap_or("")
+    }
+
+    fn arg(&self, n: usize) -> String {
+        self.cmd.split_whitespace().nth(n).unwrap_or("").to_string()
+    }
+
+    // the LUN id of `add <dev> <id> [options]`, `replace <dev> <id> [options]` or `del <id>`
+    fn lun_id(&self) -> String {
+        if self.cmd.starts_with("del") {
+            self.arg(1)
+        } else {
+            self.arg(2)
+        }
+    }
+}
+
+/// translates the errno of writing a `mgmt` file to the specific error, the kind of object
+/// is told by the directory of the `mgmt` file. `ScstError::Io` returns for unknown errnos.
+pub(crate) fn map_errno(errno: i32, context: &MgmtContext) -> ScstError {
+    let (name, lun) = (context.arg(1), context.lun_id());
+    let lun_group = context.dir_name(3) == "ini_groups";
+
+    match (context.dir_name(1), errno) {
+        ("luns", EEXIST) if lun_group => ScstError::GroupLunExists(lun),
+        ("luns", EEXIST) => ScstError::TargetLunExists(lun),
+        ("luns", ENOENT) if context.cmd.starts_with("del") && lun_group => {
+            ScstError::GroupNoLun(lun)
+        }
+        ("luns", ENOENT) if context.cmd.starts_with("del") => ScstError::TargetNoLun(lun),
+        ("luns", ENOENT) => ScstError::NoDevice(name),
+        ("luns", EBUSY) if lun_group => ScstError::GroupRemLunFail(lun),
+        ("luns", EBUSY) => ScstError::TargetRemLunFail(lun),
+        ("luns", EINVAL) => ScstError::LunBadAttrs,
+
+        ("ini_groups", EEXIST) => ScstError::GroupExists(name),
+        ("ini_groups", ENOENT) => ScstError::NoGroup(name),
+        ("ini_groups", EBUSY) => ScstError::GroupRemFail(name),
+        ("ini_groups", EINVAL) => ScstError::GroupBadAttrs,
+
+        ("initiators", EEXIST) => ScstError::GroupIniExists(name),
+        ("initiators", ENOENT) => ScstError::GroupNoIni(name),
+        ("initiators", EBUSY) => ScstError::GroupRemIniFail(name),
+        ("initiators", EINVAL) => ScstError::IniBadAttrs,
+
+        (_, EEXIST) if context.dir_name(2) == "handlers" => ScstError::DeviceExists(name),
+        (_, ENOENT) if context.dir_name(2) == "handlers" => ScstError::NoDevice(name),
+        (_, EBUSY) if context.dir_name(2) == "handlers" => ScstError::DeviceRemFail(name),
+        (_, EINVAL) if context.dir_name(2) == "handlers" => ScstError::DeviceBadAttr,
+
+        (_, EEXIST) if context.dir_name(2) == "targets" => ScstError::TargetExists(name),
+        (_, ENOENT) if context.dir_name(2) == "targets" => ScstError::NoTarget(name),
+        (_, EBUSY) if context.dir_name(2) == "targets" => ScstError::TargetBusy,
+        (_, EINVAL) if context.dir_name(2) == "targets" => ScstError::TargetBadAttrs,
+
+        (_, EINVAL) => ScstError::BadAttrs,
+        _ => ScstError::Io(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static HANDLER_HELP: &str = r#"Usage: echo "add_device device_name [parameters]" >mgmt
+       echo "del_device device_name" >mgmt
+       echo "add_attribute <attribute> <value>" >mgmt
+       echo "del_attribute <attribute> <value>" >mgmt
+       echo "add_device_attribute device_name <attribute> <value>" >mgmt
+       echo "del_device_attribute device_name <attribute> <value>" >mgmt
+where parameters are one or more param_name=value pairs separated by ';'
+
+The following parameters available: active, bind_alua_state, blocksize, cluster_mode, dif_filename, dif_mode, dif_static_app_tag, dif_type, filename, numa_node_id, nv_cache, read_only, removable, rotational, thin_provisioned, tst, write_through
+"#;
+
+    static DRIVER_HELP: &str = r#"Usage: echo "add_target target_name [parameters]" >mgmt
+       echo "del_target target_name" >mgmt
+       echo "add_attribute <attribute> <value>" >mgmt
+       echo "del_attribute <attribute> <value>" >mgmt
+       echo "add_target_attribute target_name <attribute> <value>" >mgmt
+       echo "del_target_attribute target_name <attribute> <value>" >mgmt
+where parameters are one or more param_name=value pairs separated by ';'
+
+The following target driver attributes available: IncomingUser, OutgoingUser
+The following target attributes available: IncomingUser, OutgoingUser, allowed_portal
+"#;
+
+    #[test]
+    fn parse_handler_help() {
+        let help = parse_mgmt_help(HANDLER_HELP);
+        assert_eq!(help.usage().len(), 6);
+        assert_eq!(help.usage()[0], "add_device device_name [parameters]");
+        assert_eq!(help.usage()[1], "del_device device_name");
+        assert_eq!(help.parameters().len(), 17);
+        assert!(help.parameters().contains(&"filename".to_string()));
+        assert!(help.parameters().contains(&"write_through".to_string()));
+        assert!(help.attribute_kinds().is_empty());
+    }
+
+    #[test]
+    fn parse_driver_help() {
+        let help = parse_mgmt_help(DRIVER_HELP);
+        assert_eq!(help.usage().len(), 6);
+        assert!(help.parameters().is_empty());
+        assert_eq!(help.attribute_kinds(), vec!["target", "target driver"]);
+        assert_eq!(
+            help.attributes("target driver"),
+            &["IncomingUser", "OutgoingUser"]
+        );
+        assert_eq!(
+            help.attributes("target"),
+            &["IncomingUser", "OutgoingUser", "allowed_portal"]
+        );
+        assert!(help.attributes("device").is_empty());
+    }
+
+    fn mapped(errno: i32, mgmt: &str, cmd: &str) -> ScstError {
+        map_errno(errno, &MgmtContext::new(Path::new(mgmt), cmd))
+    }
+
+    static TGT: &str = "/sys/kernel/scst_tgt/targets/iscsi/iqn.2018-11.com.vine:vol";
+
+    #[test]
+    fn map_errno_eexist() {
+        let handler = "/sys/kernel/scst_tgt/handlers/vdisk_blockio/mgmt";
+        let err = mapped(EEXIST, handler, "add_device vol filename=/dev/sdb");
+        assert!(matches!(err, ScstError::DeviceExists(n) if n == "vol"));
+
+        let driver = "/sys/kernel/scst_tgt/targets/iscsi/mgmt";
+        let err = mapped(EEXIST, driver, "add_target iqn.2018-11.com.vine:vol");
+        assert!(matches!(err, ScstError::TargetExists(n) if n == "iqn.2018-11.com.vine:vol"));
+
+        let err = mapped(EEXIST, &format!("{}/luns/mgmt", TGT), "add vol 0");
+        assert!(matches!(err, ScstError::TargetLunExists(n) if n == "0"));
+
+        let err = mapped(
+            EEXIST,
+            &format!("{}/ini_groups/grp/luns/mgmt", TGT),
+            "add vol 1",
+        );
+        assert!(matches!(err, ScstError::GroupLunExists(n) if n == "1"));
+
+        // the options after the LUN id aren't taken as the id
+        let err = mapped(
+            EEXIST,
+            &format!("{}/luns/mgmt", TGT),
+            "add vol 3 read_only=1",
+        );
+        assert!(matches!(err, ScstError::TargetLunExists(n) if n == "3"));
+        let err = mapped(
+            EEXIST,
+            &format!("{}/ini_groups/grp/luns/mgmt", TGT),
+            "replace vol 4 read_only=1",
+        );
+        assert!(matches!(err, ScstError::GroupLunExists(n) if n == "4"));
+
+        let err = mapped(EEXIST, &format!("{}/ini_groups/mgmt", TGT), "create grp");
+        assert!(matches!(err, ScstError::GroupExists(n) if n == "grp"));
+
+        let ini = format!("{}/ini_groups/grp/initiators/mgmt", TGT);
+        let err = mapped(EEXIST, &ini, "add iqn.1991-05.com.microsoft:host");
+        assert!(matches!(err, ScstError::GroupIniExists(_)));
+    }
+
+    #[test]
+    fn map_errno_enoent() {
+        let handler = "/sys/kernel/scst_tgt/handlers/vdisk_blockio/mgmt";
+        let err = mapped(ENOENT, handler, "del_device vol");
+        assert!(matches!(err, ScstError::NoDevice(n) if n == "vol"));
+
+        let driver = "/sys/kernel/scst_tgt/targets/iscsi/mgmt";
+        let err = mapped(ENOENT, driver, "del_target iqn.2018-11.com.vine:vol");
+        assert!(matches!(err, ScstError::NoTarget(_)));
+
+        let err = mapped(ENOENT, &format!("{}/luns/mgmt", TGT), "add vol 0");
+        assert!(matches!(err, ScstError::NoDevice(n) if n == "vol"));
+
+        let err = mapped(ENOENT, &format!("{}/luns/mgmt", TGT), "del 0");
+        assert!(matches!(err, ScstError::TargetNoLun(n) if n == "0"));
+
+        let err = mapped(
+            ENOENT,
+            &format!("{}/ini_groups/grp/luns/mgmt", TGT),
+            "del 2",
+        );
+        assert!(matches!(err, ScstError::GroupNoLun(n) if n == "2"));
+
+        let err = mapped(ENOENT, &format!("{}/ini_groups/mgmt", TGT), "del grp");
+        assert!(matches!(err, ScstError::NoGroup(n) if n == "grp"));
+    }
+
+    #[test]
+    fn map_errno_ebusy() {
+        let driver = "/sys/kernel/scst_tgt/targets/iscsi/mgmt";
+        let err = mapped(EBUSY, driver, "del_target iqn.2018-11.com.vine:vol");
+        assert!(matches!(err, ScstError::TargetBusy));
+
+        let handler = "/sys/kernel/scst_tgt/handlers/vdisk_blockio/mgmt";
+        let err = mapped(EBUSY, handler, "del_device vol");
+        assert!(matches!(err, ScstError::DeviceRemFail(n) if n == "vol"));
+
+        let err = mapped(EBUSY, &format!("{}/ini_groups/mgmt", TGT), "del grp");
+        assert!(matches!(err, ScstError::GroupRemFail(n) if n == "grp"));
+
+        let err = mapped(EBUSY, &format!("{}/luns/mgmt", TGT), "del 5");
+        assert!(matches!(err, ScstError::TargetRemLunFail(n) if n == "5"));
+    }
+
+    #[test]
+    fn map_errno_einval() {
+        let handler = "/sys/kernel/scst_tgt/handlers/vdisk_blockio/mgmt";
+        let err = mapped(EINVAL, handler, "add_device vol foo=1");
+        assert!(matches!(err, ScstError::DeviceBadAttr));
+
+        let driver = "/sys/kernel/scst_tgt/targets/iscsi/mgmt";
+        let err = mapped(EINVAL, driver, "add_target iqn.2018-11.com.vine:vol foo=1");
+        assert!(matches!(err, ScstError::TargetBadAttrs));
+
+        let err = mapped(EINVAL, &format!("{}/luns/mgmt", TGT), "add vol 0 foo=1");
+        assert!(matches!(err, ScstError::LunBadAttrs));
+
+        let err = mapped(EINVAL, "/sys/kernel/scst_tgt/mgmt", "foo");
+        assert!(matches!(err, ScstError::BadAttrs));
+    }
+
+    #[test]
+    fn map_errno_unknown() {
+        let err = mapped(
+            5,
+            "/sys/kernel/scst_tgt/targets/iscsi/mgmt",
+            "add_target iqn",
+        );
+        match err {
+            ScstError::Io(e) => assert_eq!(e.raw_os_error(), Some(5)),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
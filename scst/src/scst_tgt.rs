@@ -1,17 +1,189 @@
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::handler::Handler;
-use crate::target::Driver;
-use crate::{Config, CopyManager, Layer, Options, ScstError, read_dir, read_fl};
+use crate::device::Device;
+use crate::handler::{Handler, HandlerType};
+#[cfg(feature = "locking")]
+use crate::lock::ScstLock;
+use crate::target::{Driver, Target};
+use crate::{
+    ApplyError, Config, CopyManager, DeviceGroup, Layer, LunCfg, Options, ScstError, Session,
+    ValidationError, echo, parse_mgmt_help, read_dir, read_fl,
+};
 
 static SCST_ROOT_OLD: &str = "/sys/kernel/scst_tgt";
 static SCST_ROOT_NEW: &str = "/sys/devices/scst";
+static SCST_ROOT_ENV: &str = "SCST_SYSFS_ROOT";
+#[cfg(feature = "locking")]
+static SCST_LOCK_FILE: &str = "/run/scst.lock";
 static SCST_HANDLER: &str = "handlers";
 static SCST_DRIVER: &str = "targets";
+static SCST_ISCSI: &str = "iscsi";
+static SCST_COPY_MANAGER: &str = "copy_manager";
+static SCST_DEVICE_GROUP: &str = "device_groups";
+static DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// the trace flags of scst_debug.h, by the names shown in `trace_level`
+static TRACE_FLAGS: &[(&str, u64)] = &[
+    ("out_of_mem", 0x1),
+    ("minor", 0x2),
+    ("sg", 0x4),
+    ("mem", 0x8),
+    ("buff", 0x10),
+    ("entryexit", 0x20),
+    ("pid", 0x40),
+    ("line", 0x80),
+    ("function", 0x100),
+    ("debug", 0x200),
+    ("special", 0x400),
+    ("scsi", 0x800),
+    ("mgmt", 0x1000),
+    ("mgmt_dbg", 0x2000),
+    ("flow_control", 0x4000),
+    ("pr", 0x8000),
+];
+// the scst release adding the version-gated attributes
+static ATTR_VERSIONS: &[(&str, (u32, u32, u32))] = &[
+    ("dif_mode", (3, 1, 0)),
+    ("dif_type", (3, 1, 0)),
+    ("dif_static_app_tag", (3, 1, 0)),
+    ("dif_filename", (3, 1, 0)),
+    ("cluster_mode", (3, 2, 0)),
+];
+
+/// devices sharing the same backing file but reporting a different identity attribute.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inconsistency {
+    filename: String,
+    attribute: String,
+    devices: Vec<(String, String)>,
+}
+
+impl Inconsistency {
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// the mismatched attribute, `usn` or `t10_dev_id`.
+    pub fn attribute(&self) -> &str {
+        &self.attribute
+    }
+
+    /// the device names with their attribute values.
+    pub fn devices(&self) -> &[(String, String)] {
+        &self.devices
+    }
+}
+
+/// a device exposed to a single initiator by `Scst::expose()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExposeSpec {
+    pub target: String,
+    pub device: String,
+    pub initiator: String,
+    pub lun_id: u64,
+    pub read_only: bool,
+}
+
+// a LUN mapping a device, to restore it with another device name
+#[derive(Debug, Clone)]
+struct DeviceExport {
+    driver: String,
+    target: String,
+    group: Option<String>,
+    id: u64,
+    options: Options,
+    alias: Option<String>,
+}
+
+/// the outcome of `Scst::drain()`.
+#[derive(Debug, Default, Clone)]
+pub struct DrainReport {
+    disabled: Vec<String>,
+    undrained: Vec<(String, Session)>,
+    unread: Vec<(String, String)>,
+}
+
+impl DrainReport {
+    /// the targets disabled by the drain.
+    pub fn disabled(&self) -> &[String] {
+        &self.disabled
+    }
+
+    /// the sessions still connected when the drain timed out, with their target names.
+    pub fn undrained(&self) -> &[(String, Session)] {
+        &self.undrained
+    }
+
+    /// the targets whose sessions couldn't be read when the drain timed out, with the reason.
+    /// A session directory vanishing during a logout causes it.
+    pub fn unread(&self) -> &[(String, String)] {
+        &self.unread
+    }
+
+    /// all sessions drained in time.
+    pub fn is_drained(&self) -> bool {
+        self.undrained.is_empty() && self.unread.is_empty()
+    }
+}
+
+/// the outcome of `Scst::all_sessions()`.
+#[derive(Debug, Default, Clone)]
+pub struct AllSessions {
+    sessions: Vec<(String, String, Session)>,
+    warnings: Vec<String>,
+}
+
+impl AllSessions {
+    /// the sessions as `(driver, target, session)`.
+    pub fn sessions(&self) -> &[(String, String, Session)] {
+        &self.sessions
+    }
+
+    pub fn into_sessions(self) -> Vec<(String, String, Session)> {
+        self.sessions
+    }
+
+    /// the targets whose sessions couldn't be read.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// the informational attributes of the scst root, the ones absent in the SCST version are None.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    version: Option<String>,
+    build_flags: Vec<String>,
+    setup_id: Option<String>,
+    last_sysfs_mgmt_res: Option<i32>,
+}
+
+impl BuildInfo {
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// the build options listed after the version, like `EXTRACHECKS` or `DEBUG`.
+    pub fn build_flags(&self) -> &[String] {
+        &self.build_flags
+    }
+
+    pub fn setup_id(&self) -> Option<&str> {
+        self.setup_id.as_deref()
+    }
+
+    /// the result of the last `mgmt` command, 0 or a negative errno.
+    pub fn last_sysfs_mgmt_res(&self) -> Option<i32> {
+        self.last_sysfs_mgmt_res
+    }
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Scst {
@@ -19,32 +191,80 @@ pub struct Scst {
     version: String,
 
     handlers: BTreeMap<String, Handler>,
-    iscsi_driver: Driver,
+    drivers: BTreeMap<String, Driver>,
     copy_driver: CopyManager,
+    #[serde(default)]
+    device_groups: BTreeMap<String, DeviceGroup>,
+
+    #[serde(skip)]
+    warnings: Vec<String>,
+    #[cfg(feature = "locking")]
+    #[serde(skip)]
+    lock: Option<ScstLock>,
+}
+
+// the sysfs root given by `SCST_SYSFS_ROOT`, or the detected one.
+fn default_root() -> PathBuf {
+    if let Some(root) = std::env::var_os(SCST_ROOT_ENV).filter(|root| !root.is_empty()) {
+        return PathBuf::from(root);
+    }
+
+    select_root(Path::new(SCST_ROOT_OLD), Path::new(SCST_ROOT_NEW)).to_path_buf()
+}
+
+// prefers the legacy sysfs root when both exist, as a kernel may keep the old root as a link.
+fn select_root<'a>(legacy: &'a Path, modern: &'a Path) -> &'a Path {
+    if legacy.exists() { legacy } else { modern }
 }
 
 impl Scst {
-    /// initizatation scst
+    /// initizatation scst. The sysfs root is searched in order of precedence: the path given
+    /// by the environment variable `SCST_SYSFS_ROOT`, the legacy `/sys/kernel/scst_tgt` and
+    /// the modern `/sys/devices/scst`.
     /// ```no_run
     /// use scst::Scst;
     ///
     /// let scst = Scst::init()?:
     /// ```
     pub fn init() -> Result<Self> {
-        let mut scst_root = Path::new(SCST_ROOT_OLD);
+        Scst::init_at(default_root())
+    }
+
+    /// initizatation scst like `Scst::init()`, holding the advisory lock `/run/scst.lock` until
+    /// the `Scst` drops, so the mgmt writes of another locked `Scst` can't race with its ones.
+    /// `ScstError::Locked` returns if the lock is held already. Reads aren't locked.
+    #[cfg(feature = "locking")]
+    pub fn init_locked() -> Result<Self> {
+        Scst::init_locked_at(default_root(), SCST_LOCK_FILE)
+    }
+
+    /// initizatation scst from the given sysfs root, holding the given lock file.
+    #[cfg(feature = "locking")]
+    pub fn init_locked_at<P: AsRef<Path>, L: AsRef<Path>>(root: P, lock_file: L) -> Result<Self> {
+        let lock = ScstLock::acquire(lock_file)?;
+        let mut scst = Scst::init_at(root)?;
+        scst.lock = Some(lock);
+
+        Ok(scst)
+    }
+
+    /// initizatation scst from the given sysfs root, like a fixture or a containerized sysfs.
+    pub fn init_at<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let scst_root = root.as_ref();
         if !scst_root.exists() {
-            scst_root = Path::new(SCST_ROOT_NEW);
-            if !scst_root.exists() {
-                anyhow::bail!(ScstError::NoModule);
-            }
+            anyhow::bail!(ScstError::NoModule);
         }
 
         let mut scst = Scst {
             root: scst_root.to_string_lossy().to_string(),
             version: "".to_string(),
             handlers: BTreeMap::new(),
-            iscsi_driver: Driver::default(),
+            drivers: BTreeMap::new(),
             copy_driver: CopyManager::default(),
+            device_groups: BTreeMap::new(),
+            warnings: Vec::new(),
+            #[cfg(feature = "locking")]
+            lock: None,
         };
         scst.load(scst_root)?;
 
@@ -55,10 +275,117 @@ impl Scst {
         &self.version
     }
 
+    /// reads the version, build flags and other informational attributes of the scst root,
+    /// which helps to correlate a behavior with the kernel build.
+    pub fn build_info(&self) -> Result<BuildInfo> {
+        if !self.root().is_dir() {
+            anyhow::bail!(ScstError::NoModule)
+        }
+
+        let mut lines = fs::read_to_string(self.root().join("version"))
+            .map(|text| {
+                text.lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default()
+            .into_iter();
+
+        Ok(BuildInfo {
+            version: lines.next(),
+            build_flags: lines.collect(),
+            setup_id: read_fl(self.root().join("setup_id")).ok(),
+            last_sysfs_mgmt_res: read_fl(self.root().join("last_sysfs_mgmt_res"))
+                .ok()
+                .and_then(|res| res.parse::<i32>().ok()),
+        })
+    }
+
+    /// the bitmask of the kernel debug logging. SCST shows the flags by names like
+    /// `out_of_mem | minor`, the unknown names are skipped.
+    pub fn trace_level(&self) -> Result<u64> {
+        let level = read_fl(self.root().join("trace_level"))?;
+        if let Some(hex) = level.strip_prefix("0x") {
+            return Ok(u64::from_str_radix(hex, 16)?);
+        }
+        if let Ok(value) = level.parse::<u64>() {
+            return Ok(value);
+        }
+
+        Ok(level
+            .split('|')
+            .filter_map(|name| {
+                TRACE_FLAGS
+                    .iter()
+                    .find(|(flag, _)| *flag == name.trim())
+                    .map(|(_, bit)| *bit)
+            })
+            .fold(0, |acc, bit| acc | bit))
+    }
+
+    /// sets the bitmask of the kernel debug logging, see `trace_level()`.
+    pub fn set_trace_level(&mut self, level: u64) -> Result<()> {
+        let cmd = format!("value {:#x}", level);
+        echo(self.root().join("trace_level"), cmd.as_str().into())
+            .map_err(|e| e.context(ScstError::SetattrFail("trace_level".to_string())))?;
+
+        Ok(())
+    }
+
+    /// the non-fatal problems found by the last load, like an unreadable version file.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// checks the attribute `attr` can be written to the object at `path_hint`, which is
+    /// relative to the scst root like `handlers/vdisk_blockio/disk1`. False returns if the
+    /// scst version is older than the release adding the attribute, or the attribute file
+    /// is absent from an existing object. For an object not created yet, only the version is
+    /// checked for the known attributes.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let scst = Scst::init()?;
+    ///     if scst.supports_attribute("handlers/vdisk_blockio/disk1", "cluster_mode") {
+    ///         println!("cluster_mode is supported");
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn supports_attribute<P: AsRef<Path>, S: AsRef<str>>(&self, path_hint: P, attr: S) -> bool {
+        let attr = attr.as_ref();
+        let since = ATTR_VERSIONS
+            .iter()
+            .find(|(name, _)| *name == attr)
+            .map(|(_, since)| *since);
+        if let (Some(since), Some(version)) = (since, parse_version(&self.version)) {
+            if version < since {
+                return false;
+            }
+        }
+
+        let object = Path::new(&self.root).join(path_hint);
+        if object.is_dir() {
+            return object.join(attr).is_file();
+        }
+
+        since.is_some()
+    }
+
     pub fn handlers(&self) -> Vec<&Handler> {
         self.handlers.values().collect()
     }
 
+    /// the names of the handlers loaded now, the other handler modules may be loaded on
+    /// demand.
+    pub fn loaded_handlers(&self) -> Vec<&str> {
+        self.handlers.keys().map(|name| name.as_str()).collect()
+    }
+
     /// get scst handler
     pub fn get_handler<S: AsRef<str>>(&self, name: S) -> Result<&Handler> {
         self.handlers
@@ -72,26 +399,352 @@ impl Scst {
             .context(ScstError::NoHandler(name.as_ref().to_string()))
     }
 
-    /// get iscsi driver
+    /// lists the devices of the handlers in the given type, like all `vdisk_blockio` devices.
+    pub fn devices_of_type(&self, handler_type: &HandlerType) -> Vec<(&Handler, &Device)> {
+        self.handlers
+            .values()
+            .filter(|h| h.handler_type() == handler_type)
+            .flat_map(|h| h.devices().into_iter().map(move |dev| (h, dev)))
+            .collect()
+    }
+
+    /// lists the devices exposing the given filename under every handler. The filenames are
+    /// compared after canonicalized, so a block device exposed twice through different paths
+    /// is detected.
+    pub fn devices_for_filename<S: AsRef<Path>>(&self, filename: S) -> Vec<(&Handler, &Device)> {
+        let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+        let filename = canonical(filename.as_ref());
+
+        self.handlers
+            .values()
+            .flat_map(|h| h.devices().into_iter().map(move |dev| (h, dev)))
+            .filter(|(_, dev)| {
+                !dev.filename().as_os_str().is_empty() && canonical(dev.filename()) == filename
+            })
+            .collect()
+    }
+
+    /// checks the devices backed by the same file report the same `usn` and `t10_dev_id`,
+    /// which multipath requires for all paths to a volume.
+    pub fn check_multipath_consistency(&self) -> Vec<Inconsistency> {
+        let mut by_filename: BTreeMap<&Path, Vec<&Device>> = BTreeMap::new();
+        for device in self.handlers.values().flat_map(|h| h.devices()) {
+            if device.filename().as_os_str().is_empty() {
+                continue;
+            }
+            by_filename
+                .entry(device.filename())
+                .or_default()
+                .push(device);
+        }
+
+        let mut out = Vec::new();
+        for (filename, devices) in by_filename.iter().filter(|(_, devs)| devs.len() > 1) {
+            for attr in ["usn", "t10_dev_id"] {
+                let values = devices
+                    .iter()
+                    .map(|dev| match attr {
+                        "usn" => dev.usn(),
+                        _ => dev.t10_dev_id(),
+                    })
+                    .collect::<Vec<&str>>();
+                if values.iter().all(|v| *v == values[0]) {
+                    continue;
+                }
+                out.push(Inconsistency {
+                    filename: filename.to_string_lossy().to_string(),
+                    attribute: attr.to_string(),
+                    devices: devices
+                        .iter()
+                        .zip(values)
+                        .map(|(dev, v)| (dev.name().to_string(), v.to_string()))
+                        .collect(),
+                });
+            }
+        }
+
+        out
+    }
+
+    /// ALUA device groups
+    pub fn device_groups(&self) -> Vec<&DeviceGroup> {
+        self.device_groups.values().collect()
+    }
+
+    pub fn get_device_group<S: AsRef<str>>(&self, name: S) -> Result<&DeviceGroup> {
+        self.device_groups
+            .get(name.as_ref())
+            .context(ScstError::NoDeviceGroup(name.as_ref().to_string()))
+    }
+
+    /// the copy manager, its LUNs are the devices available for EXTENDED COPY.
+    pub fn copy_manager(&self) -> &CopyManager {
+        &self.copy_driver
+    }
+
+    pub fn copy_manager_mut(&mut self) -> &mut CopyManager {
+        &mut self.copy_driver
+    }
+
+    /// every loaded target driver, sorted by name. copy_manager isn't included, see `to_cfg()`.
+    pub fn drivers(&self) -> Vec<&Driver> {
+        self.drivers.values().collect()
+    }
+
+    /// the sessions of every target as `(driver, target, session)`, like for a global
+    /// connection view. The targets whose sessions can't be read are skipped, and reported in
+    /// `AllSessions::warnings()`.
+    pub fn all_sessions(&self) -> Result<AllSessions> {
+        let mut all = AllSessions::default();
+        for driver in self.drivers.values() {
+            for target in driver.targets() {
+                match target.sessions() {
+                    Ok(sessions) => all.sessions.extend(sessions.into_iter().map(|session| {
+                        (
+                            driver.name().to_string(),
+                            target.name().to_string(),
+                            session,
+                        )
+                    })),
+                    Err(e) => all.warnings.push(format!(
+                        "failed to read sessions of target {}/{}: {}",
+                        driver.name(),
+                        target.name(),
+                        e
+                    )),
+                }
+            }
+        }
+
+        Ok(all)
+    }
+
+    /// get the target driver by name, like `iscsi`, `qla2x00t` or `ib_srpt`.
+    pub fn get_driver<S: AsRef<str>>(&self, name: S) -> Result<&Driver> {
+        self.drivers
+            .get(name.as_ref())
+            .context(ScstError::NoDriver(name.as_ref().to_string()))
+    }
+
+    pub fn get_driver_mut<S: AsRef<str>>(&mut self, name: S) -> Result<&mut Driver> {
+        self.drivers
+            .get_mut(name.as_ref())
+            .context(ScstError::NoDriver(name.as_ref().to_string()))
+    }
+
+    /// get iscsi driver, it's always loaded by `Scst::init()`. An `Scst` not initialized, like
+    /// a default or deserialized one, gives an empty driver.
     pub fn iscsi(&self) -> &Driver {
-        &self.iscsi_driver
+        static UNLOADED: OnceLock<Driver> = OnceLock::new();
+        self.drivers
+            .get(SCST_ISCSI)
+            .unwrap_or_else(|| UNLOADED.get_or_init(Driver::default))
     }
 
+    /// like `iscsi()`, an `Scst` not initialized gets an empty driver at the iscsi directory of
+    /// its root, so the writes fail as the driver isn't loaded.
     pub fn iscsi_mut(&mut self) -> &mut Driver {
-        &mut self.iscsi_driver
+        let root = Path::new(&self.root).join(SCST_DRIVER).join(SCST_ISCSI);
+        self.drivers
+            .entry(SCST_ISCSI.to_string())
+            .or_insert_with(|| Driver::unloaded(root))
     }
 
-    /// add a device for handler.
+    /// gives the initiator access to the device through a target of the driver. The target, a
+    /// group named by the initiator, the LUN and the initiator are created unless they exist,
+    /// and the target is enabled, so calling it again is a no-op. `ScstError::GroupLunExists`
+    /// returns if the LUN id is taken by another device.
     ///
     /// ```no_run
-    /// use scst::{Scst, Options};
+    /// use anyhow::Result;
+    /// use scst::{ExposeSpec, Scst};
     ///
-    /// let mut scst = Scst::init()?;
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///     scst.expose("iscsi", ExposeSpec {
+    ///         target: "iqn.2018-11.com.vine:test".to_string(),
+    ///         device: "disk1".to_string(),
+    ///         initiator: "iqn.1988-12.com.oracle:d4ebaa45254".to_string(),
+    ///         lun_id: 0,
+    ///         read_only: false,
+    ///     })?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn expose<S: AsRef<str>>(&mut self, driver: S, spec: ExposeSpec) -> Result<()> {
+        if self.find_device(&spec.device).is_none() {
+            anyhow::bail!(ScstError::NoDevice(spec.device))
+        }
+
+        let driver = self.get_driver_mut(driver)?;
+        if driver.get_target(&spec.target).is_err() {
+            driver.add_target(&spec.target, &Options::new())?;
+        }
+        let target = driver.get_target_mut(&spec.target)?;
+        if target.get_ini_group(&spec.initiator).is_err() {
+            target.create_ini_group(&spec.initiator)?;
+        }
+
+        let group = target.get_ini_group_mut(&spec.initiator)?;
+        match group.get_lun_by_id(spec.lun_id) {
+            Ok(lun) if lun.device() == spec.device => {}
+            Ok(_) => anyhow::bail!(ScstError::GroupLunExists(spec.lun_id.to_string())),
+            Err(_) => {
+                let mut options = Options::new();
+                if spec.read_only {
+                    options.insert("read_only", "1");
+                }
+                group.add_lun(spec.device.as_str(), spec.lun_id, &options)?;
+            }
+        }
+        if !group.contains_initiator(&spec.initiator) {
+            group.add_initiator(&spec.initiator)?;
+        }
+
+        if !target.enabled() {
+            target.enable()?;
+        }
+
+        Ok(())
+    }
+
+    /// revokes the access given by `Scst::expose()`. The initiator leaves its group, and once
+    /// the group has no initiators its LUNs of the device are deleted and the emptied group is
+    /// removed. The target and the device are kept. Nothing happens if the initiator isn't in
+    /// any group of the target.
+    pub fn unexpose<S: AsRef<str>>(
+        &mut self,
+        driver: S,
+        target: S,
+        device: S,
+        initiator: S,
+    ) -> Result<()> {
+        let (device, initiator) = (device.as_ref(), initiator.as_ref());
+        let target = self.get_driver_mut(driver)?.get_target_mut(target)?;
+        let name = match target
+            .ini_groups()
+            .into_iter()
+            .find(|g| g.contains_initiator(initiator))
+        {
+            Some(group) => group.name().to_string(),
+            None => return Ok(()),
+        };
+
+        let group = target.get_ini_group_mut(&name)?;
+        group.del_initiator(initiator)?;
+        if !group.initiators().is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<u64> = group
+            .luns()
+            .into_iter()
+            .filter(|lun| lun.device() == device)
+            .map(|lun| lun.id())
+            .collect();
+        for id in ids {
+            group.del_lun_force(id)?;
+        }
+
+        if group.luns().is_empty() {
+            target.del_ini_group(&name)?;
+        }
+
+        Ok(())
+    }
+
+    /// shuts scst down for maintenance: disables the targets of every driver, waits up to
+    /// `timeout` for their sessions to drain, then disables the enabled drivers. The drivers are
+    /// disabled even if some sessions are left, they are returned in the report.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let report = scst.drain(Duration::from_secs(30))?;
+    ///     for (target, session) in report.undrained() {
+    ///         println!("{}: {} not drained", target, session.initiator_name());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn drain(&mut self, timeout: Duration) -> Result<DrainReport> {
+        let mut report = DrainReport::default();
+        for driver in self.drivers.values_mut() {
+            let names = driver
+                .targets()
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect::<Vec<String>>();
+            for name in names {
+                let target = driver.get_target_mut(&name)?;
+                if target.enabled() {
+                    target.disable()?;
+                    report.disabled.push(name);
+                }
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let busy = self
+                .drivers
+                .values()
+                .flat_map(|driver| driver.targets())
+                .filter(|t| t.session_count().unwrap_or(0) > 0)
+                .collect::<Vec<&Target>>();
+            if busy.is_empty() {
+                break;
+            }
+            if Instant::now() >= deadline {
+                for target in busy {
+                    match target.sessions() {
+                        Ok(sessions) => report.undrained.extend(
+                            sessions
+                                .into_iter()
+                                .map(|session| (target.name().to_string(), session)),
+                        ),
+                        Err(e) => report
+                            .unread
+                            .push((target.name().to_string(), format!("{:#}", e))),
+                    }
+                }
+                break;
+            }
+            thread::sleep(
+                DRAIN_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+            );
+        }
+
+        for driver in self.drivers.values_mut().filter(|d| d.enabled()) {
+            driver.disable()?;
+        }
+
+        Ok(report)
+    }
+
+    /// add a device for handler, returns the new device. `ScstError::NoHandler` returns with a
+    /// hint to load the module if the handler isn't loaded.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Options, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
     ///
-    /// let mut options = Options::new();
-    /// options.insert("read_only", "1");
+    ///     let mut options = Options::new();
+    ///     options.insert("read_only", "1");
     ///
-    /// scst.add_device("vdisk_blockio", "disk1", "/dev/sdb", &options)?;
+    ///     scst.add_device("vdisk_blockio", "disk1", "/dev/sdb", &options)?
+    ///         .set_attribute("rotational", "0")?;
+    ///     Ok(())
+    /// }
     /// ```
     pub fn add_device<S: AsRef<str>>(
         &mut self,
@@ -99,9 +752,18 @@ impl Scst {
         name: S,
         filename: S,
         options: &Options,
-    ) -> Result<()> {
+    ) -> Result<&mut Device> {
         let handler_ref = handler.as_ref();
         let name_ref = name.as_ref();
+        if !self.handlers.contains_key(handler_ref) {
+            return Err(
+                anyhow::Error::new(ScstError::NoHandler(handler_ref.to_string())).context(format!(
+                    "handler '{}' isn't loaded, try 'modprobe {}'",
+                    handler_ref,
+                    handler_module(handler_ref)
+                )),
+            );
+        }
 
         let handler = self.get_handler_mut(handler_ref)?;
         handler.add_device(name_ref, filename.as_ref(), options)?;
@@ -109,10 +771,11 @@ impl Scst {
         self.copy_driver
             .load(self.copy_driver.root().to_path_buf())?;
 
-        Ok(())
+        self.get_handler_mut(handler_ref)?.get_device_mut(name_ref)
     }
 
-    /// delete device for handler
+    /// delete device for handler. `ScstError::DeviceInUse` returns with the LUN still mapping
+    /// the device, delete the LUNs firstly.
     ///
     /// ```no_run
     /// use scst::{Scst, Options};
@@ -123,6 +786,26 @@ impl Scst {
     /// ```
     pub fn del_device<S: AsRef<str>>(&mut self, handler: S, name: S) -> Result<()> {
         let handler_ref = handler.as_ref();
+        if let Some(row) = self
+            .inventory()
+            .iter()
+            .find(|r| r.device() == name.as_ref())
+        {
+            let lun = match row.group() {
+                Some(group) => format!(
+                    "{}/{}/{}/lun{}",
+                    row.driver(),
+                    row.target(),
+                    group,
+                    row.lun()
+                ),
+                None => format!("{}/{}/lun{}", row.driver(), row.target(), row.lun()),
+            };
+            anyhow::bail!(ScstError::DeviceInUse {
+                device: name.as_ref().to_string(),
+                lun,
+            })
+        }
         let handler = self.get_handler_mut(handler_ref)?;
 
         handler.del_device(name.as_ref())?;
@@ -132,89 +815,516 @@ impl Scst {
 
         Ok(())
     }
-}
 
-impl Scst {
-    /// loads scst configuration scst from `Config`
+    /// renames a device by recreating it, since SCST fixes the name at creation. The device
+    /// is recreated with its filename, its `add_device` options and its other writable
+    /// attributes, like `t10_dev_id` and `usn`, so the initiators see the same identity. The
+    /// LUNs exporting it through every driver are restored with the new name, keeping their
+    /// options and alias. On a failure the old device and its LUNs are restored.
+    ///
+    /// NOTE: the LUNs are deleted with the device and added back, so the I/O to them is
+    /// interrupted briefly.
+    ///
     /// ```no_run
     /// use anyhow::Result;
     /// use scst::Scst;
     ///
     /// fn main() -> Result<()> {
     ///     let mut scst = Scst::init()?;
-    ///
-    ///     let cfg = Config::read("/tmp/scst.yml")?;
-    ///     scst1.from_cfg(&cfg)?;
-    ///
+    ///     scst.rename_device("vdisk_blockio", "disk1", "disk2")?;
     ///     Ok(())
     /// }
     /// ```
-    pub fn from_cfg(&mut self, cfg: &Config) -> Result<()> {
-        for hc in cfg.handlers() {
-            let handler = self.get_handler_mut(hc.name())?;
-            for dev in hc.devices() {
-                if handler.get_device(dev.name()).is_err() {
-                    let opts = Options::new();
-                    handler.add_device(dev.name(), dev.filename(), &opts)?
-                }
+    pub fn rename_device<S: AsRef<str>>(&mut self, handler: S, old: S, new: S) -> Result<()> {
+        let (handler_ref, old_ref, new_ref) = (handler.as_ref(), old.as_ref(), new.as_ref());
+        let handler = self.get_handler_mut(handler_ref)?;
+        if handler.get_device(new_ref).is_ok() {
+            anyhow::bail!(ScstError::DeviceExists(new_ref.to_string()))
+        }
+
+        let device = handler.get_device(old_ref)?;
+        let filename = device.filename().to_string_lossy().to_string();
+        let (options, attrs) = device.recreate_options();
+        let exports = self.device_exports(old_ref);
+
+        for (i, export) in exports.iter().enumerate() {
+            if let Err(e) = self.del_export(export) {
+                self.add_exports(old_ref, &exports[..i]).ok();
+                return Err(e);
             }
         }
+        if let Err(e) = self.get_handler_mut(handler_ref)?.del_device(old_ref) {
+            self.add_exports(old_ref, &exports).ok();
+            return Err(e);
+        }
 
-        for dc in cfg.drivers() {
-            let driver = { self.iscsi_mut() };
-            if dc.enabled() == 1 {
-                driver.enable()?;
+        let res = self
+            .recreate_device(handler_ref, new_ref, &filename, &options, &attrs)
+            .and_then(|_| self.add_exports(new_ref, &exports));
+        if let Err(e) = res {
+            for export in &exports {
+                if self.export_device(export).as_deref() == Some(new_ref) {
+                    self.del_export(export).ok();
+                }
+            }
+            if let Ok(handler) = self.get_handler_mut(handler_ref) {
+                if handler.get_device(new_ref).is_ok() {
+                    handler.del_device(new_ref).ok();
+                }
             }
+            self.recreate_device(handler_ref, old_ref, &filename, &options, &attrs)
+                .and_then(|_| self.add_exports(old_ref, &exports))
+                .ok();
+            return Err(e);
+        }
 
-            for tc in dc.targets() {
-                let target = {
-                    let mut res = driver.get_target_mut(tc.name());
-                    if res.is_err() {
-                        let opts = Options::new();
-                        res = driver.add_target(tc.name(), &opts);
-                    }
-                    res?
-                };
+        let root = self.copy_driver.root().to_path_buf();
+        self.copy_driver.load(root)?;
 
-                for lc in tc.luns() {
-                    let name = format!("lun {}", lc.id());
-                    if target.get_lun(&name).is_err() {
-                        let opts = Options::new();
-                        target.add_lun(lc.device(), lc.id(), &opts)?;
+        Ok(())
+    }
+
+    // the LUNs mapping the device through every driver
+    fn device_exports(&self, device: &str) -> Vec<DeviceExport> {
+        let mut exports = Vec::new();
+        for driver in self.drivers() {
+            for target in driver.targets() {
+                let groups = std::iter::once((None, target.luns())).chain(
+                    target
+                        .ini_groups()
+                        .into_iter()
+                        .map(|group| (Some(group.name().to_string()), group.luns())),
+                );
+                for (group, luns) in groups {
+                    for lun in luns.into_iter().filter(|l| l.device() == device) {
+                        exports.push(DeviceExport {
+                            driver: driver.name().to_string(),
+                            target: target.name().to_string(),
+                            group: group.clone(),
+                            id: lun.id(),
+                            options: lun.add_options(),
+                            alias: lun.alias().map(|alias| alias.to_string()),
+                        });
                     }
                 }
+            }
+        }
 
-                for gc in tc.groups() {
-                    let group = {
-                        let mut res = target.get_ini_group_mut(gc.name());
+        exports
+    }
+
+    // the device mapped by the LUN of the export now, None if the LUN is gone
+    fn export_device(&self, export: &DeviceExport) -> Option<String> {
+        let target = self
+            .get_driver(&export.driver)
+            .ok()?
+            .get_target(&export.target)
+            .ok()?;
+        let lun = match &export.group {
+            Some(group) => target.get_ini_group(group).ok()?.get_lun_by_id(export.id),
+            None => target.get_lun_by_id(export.id),
+        };
+
+        lun.ok().map(|lun| lun.device().to_string())
+    }
+
+    fn del_export(&mut self, export: &DeviceExport) -> Result<()> {
+        let target = self
+            .get_driver_mut(&export.driver)?
+            .get_target_mut(&export.target)?;
+        match &export.group {
+            Some(group) => target.get_ini_group_mut(group)?.del_lun_force(export.id),
+            None => target.del_lun_force(export.id),
+        }
+    }
+
+    fn add_exports(&mut self, device: &str, exports: &[DeviceExport]) -> Result<()> {
+        for export in exports {
+            let target = self
+                .get_driver_mut(&export.driver)?
+                .get_target_mut(&export.target)?;
+            let lun = match &export.group {
+                Some(group) => {
+                    let group = target.get_ini_group_mut(group)?;
+                    group.add_lun(device, export.id, &export.options)?;
+                    group.get_lun_by_id_mut(export.id)?
+                }
+                None => {
+                    target.add_lun(device, export.id, &export.options)?;
+                    target.get_lun_by_id_mut(export.id)?
+                }
+            };
+            if let Some(alias) = &export.alias {
+                lun.set_alias(alias)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // adds the device, then sets the attributes which differ from the ones it's created with
+    fn recreate_device(
+        &mut self,
+        handler: &str,
+        name: &str,
+        filename: &str,
+        options: &Options,
+        attrs: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let device = self
+            .get_handler_mut(handler)?
+            .add_device(name, filename, options)?;
+        for (attr, value) in attrs {
+            let current = read_fl(device.root().join(attr)).ok();
+            if current.as_deref() != Some(value.as_str()) {
+                device.set_attribute(attr.as_str(), value.as_str())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Scst {
+    /// loads scst configuration scst from `Config`
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let cfg = Config::read("/tmp/scst.yml")?;
+    ///     scst1.from_cfg(&cfg)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_cfg(&mut self, cfg: &Config) -> Result<()> {
+        self.from_cfg_devices_only(cfg)?;
+        self.from_cfg_drivers_only(cfg)
+    }
+
+    /// loads only the handler devices from `Config`, drivers and targets are left untouched.
+    /// Together with `from_cfg_drivers_only()` it allows to provision and verify the devices
+    /// before exposing them.
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Config, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let cfg = Config::read_file("/tmp/scst.yml")?;
+    ///     scst.from_cfg_devices_only(&cfg)?;
+    ///     scst.from_cfg_drivers_only(&cfg)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_cfg_devices_only(&mut self, cfg: &Config) -> Result<()> {
+        self.apply_cfg_devices(cfg, |_, e| Err(e))?;
+
+        let root = self.copy_driver.root().to_path_buf();
+        self.copy_driver.load(root)?;
+
+        Ok(())
+    }
+
+    /// loads only the drivers and targets from `Config`, the devices referenced by LUNs must
+    /// already exist.
+    pub fn from_cfg_drivers_only(&mut self, cfg: &Config) -> Result<()> {
+        self.apply_cfg_drivers(cfg, |_, e| Err(e))?;
+
+        let root = self.copy_driver.root().to_path_buf();
+        self.copy_driver.load(root)?;
+
+        Ok(())
+    }
+
+    // creates the missing devices of `Config`. Every failure is handed to `fail` with the
+    // failing object, the walk stops when `fail` returns an error and skips the object else.
+    fn apply_cfg_devices<F>(&mut self, cfg: &Config, mut fail: F) -> Result<()>
+    where
+        F: FnMut(String, anyhow::Error) -> Result<()>,
+    {
+        for hc in cfg.handlers() {
+            let handler = match self.get_handler_mut(hc.name()) {
+                Ok(handler) => handler,
+                Err(e) => {
+                    fail(hc.name().to_string(), e)?;
+                    continue;
+                }
+            };
+            for dev in hc.devices() {
+                if handler.get_device(dev.name()).is_err() {
+                    let opts = Options::new();
+                    if let Err(e) = handler.add_device(dev.name(), dev.filename(), &opts) {
+                        fail(format!("{}/{}", hc.name(), dev.name()), e)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // applies the drivers, targets, LUNs and groups of `Config`, handing failures to `fail`
+    // like `apply_cfg_devices()`.
+    fn apply_cfg_drivers<F>(&mut self, cfg: &Config, mut fail: F) -> Result<()>
+    where
+        F: FnMut(String, anyhow::Error) -> Result<()>,
+    {
+        for dc in cfg.drivers() {
+            // the LUNs of copy_manager follow the devices, it's reloaded by the callers
+            if dc.name() == self.copy_driver.name() {
+                continue;
+            }
+            let driver = match self.get_driver_mut(dc.name()) {
+                Ok(driver) => driver,
+                Err(e) => {
+                    fail(dc.name().to_string(), e)?;
+                    continue;
+                }
+            };
+            if dc.enabled() == 1 {
+                if let Err(e) = driver.enable() {
+                    fail(dc.name().to_string(), e)?;
+                }
+            }
+
+            for tc in dc.targets() {
+                let tpath = format!("{}/{}", dc.name(), tc.name());
+                let target = {
+                    let mut res = driver.get_target_mut(tc.name());
+                    if res.is_err() {
+                        let opts = Options::new();
+                        res = driver.add_target(tc.name(), &opts);
+                    }
+                    match res {
+                        Ok(target) => target,
+                        Err(e) => {
+                            fail(tpath, e)?;
+                            continue;
+                        }
+                    }
+                };
+                // the unset id of an old config is 0, which is never valid
+                if tc.rel_tgt_id() != 0 && tc.rel_tgt_id() != target.rel_tgt_id() {
+                    if let Err(e) = apply_rel_tgt_id(target, tc.rel_tgt_id()) {
+                        fail(format!("{}/rel_tgt_id", tpath), e)?;
+                    }
+                }
+
+                for lc in tc.luns() {
+                    if let Err(e) = target.ensure_lun(lc.device(), lc.id(), &lc.options()) {
+                        fail(format!("{}/lun{}", tpath, lc.id()), e)?;
+                    }
+                }
+
+                for gc in tc.groups() {
+                    let gpath = format!("{}/{}", tpath, gc.name());
+                    let group = {
+                        let mut res = target.get_ini_group_mut(gc.name());
                         if res.is_err() {
                             res = target.create_ini_group(gc.name());
                         }
-                        res?
+                        match res {
+                            Ok(group) => group,
+                            Err(e) => {
+                                fail(gpath, e)?;
+                                continue;
+                            }
+                        }
                     };
 
                     for lc in gc.luns() {
-                        let name = format!("lun {}", lc.id());
-                        if group.get_lun(&name).is_err() {
-                            let opts = Options::new();
-                            group.add_lun(lc.device(), lc.id(), &opts)?;
+                        if let Err(e) = group.ensure_lun(lc.device(), lc.id(), &lc.options()) {
+                            fail(format!("{}/lun{}", gpath, lc.id()), e)?;
                         }
                     }
 
                     for ini in gc.initiators() {
-                        if !group.initiators().contains(&ini.to_string()) {
-                            group.add_initiator(ini.to_string())?;
+                        if !group.contains_initiator(ini) {
+                            if let Err(e) = group.add_initiator(ini) {
+                                fail(format!("{}/{}", gpath, ini), e)?;
+                            }
                         }
                     }
                 }
 
                 if tc.enabled() == 1 {
-                    target.enable()?
+                    if let Err(e) = target.enable() {
+                        fail(tpath, e)?;
+                    }
                 }
             }
+        }
 
-            self.copy_driver
-                .load(self.copy_driver.root().to_path_buf())?;
+        Ok(())
+    }
+
+    /// cross-checks `Config` against the live system before applying it, so no write happens
+    /// for a config referencing an unloaded handler or driver, a missing device, an
+    /// `add_device` parameter the handler doesn't list in its `mgmt`, or an attribute like
+    /// `enabled` the driver or target doesn't expose.
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Config, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let cfg = Config::read_file("/tmp/scst.yml")?;
+    ///     if let Err(errs) = scst.validate_cfg(&cfg) {
+    ///         for e in errs {
+    ///             eprintln!("{}", e);
+    ///         }
+    ///         return Ok(());
+    ///     }
+    ///     scst.from_cfg(&cfg)
+    /// }
+    /// ```
+    pub fn validate_cfg(&self, cfg: &Config) -> std::result::Result<(), Vec<ValidationError>> {
+        let mut errs = Vec::new();
+
+        let mut devices = Vec::new();
+        for hc in cfg.handlers() {
+            devices.extend(hc.devices().into_iter().map(|dev| dev.name()));
+            let handler = match self.handlers.get(hc.name()) {
+                Some(handler) => handler,
+                None => {
+                    errs.push(ValidationError::NoHandler(hc.name().to_string()));
+                    continue;
+                }
+            };
+            // an unreadable `mgmt` or one listing no parameters can't tell
+            let params = handler
+                .mgmt_help()
+                .map(|text| parse_mgmt_help(&text).parameters().to_vec())
+                .unwrap_or_default();
+            if params.is_empty() {
+                continue;
+            }
+            for dev in hc.devices() {
+                let unsupported = !dev.filename().is_empty()
+                    && handler.get_device(dev.name()).is_err()
+                    && !params.iter().any(|param| param == "filename");
+                if unsupported {
+                    errs.push(ValidationError::UnsupportedParam {
+                        object: format!("{}/{}", hc.name(), dev.name()),
+                        param: "filename".to_string(),
+                    });
+                }
+            }
+        }
+
+        for dc in cfg.drivers() {
+            // the attributes of copy_manager aren't applied, only its LUNs are checked
+            let driver = self.drivers.get(dc.name());
+            if driver.is_none() && dc.name() != self.copy_driver.name() {
+                errs.push(ValidationError::NoDriver(dc.name().to_string()));
+                continue;
+            }
+            let unexposed = |target: Option<&str>, attr: &str| match (driver, target) {
+                (Some(driver), None) => driver.attribute(attr).is_none(),
+                (Some(driver), Some(target)) => driver
+                    .get_target(target)
+                    .is_ok_and(|target| target.attribute(attr).is_none()),
+                (None, _) => false,
+            };
+            if dc.enabled() == 1 && unexposed(None, "enabled") {
+                errs.push(ValidationError::UnsupportedAttr {
+                    object: dc.name().to_string(),
+                    attr: "enabled".to_string(),
+                });
+            }
+
+            for tc in dc.targets() {
+                let target = format!("{}/{}", dc.name(), tc.name());
+                if tc.enabled() == 1 && unexposed(Some(tc.name()), "enabled") {
+                    errs.push(ValidationError::UnsupportedAttr {
+                        object: target.clone(),
+                        attr: "enabled".to_string(),
+                    });
+                }
+                for lc in tc.luns() {
+                    let object = format!("{}/lun{}", target, lc.id());
+                    errs.extend(self.validate_lun_cfg(object, lc, &devices));
+                }
+                for gc in tc.groups() {
+                    for lc in gc.luns() {
+                        let object = format!("{}/{}/lun{}", target, gc.name(), lc.id());
+                        errs.extend(self.validate_lun_cfg(object, lc, &devices));
+                    }
+                }
+            }
+        }
+
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+
+        Ok(())
+    }
+
+    fn validate_lun_cfg(
+        &self,
+        object: String,
+        lc: &LunCfg,
+        devices: &[&str],
+    ) -> Option<ValidationError> {
+        let device = lc.device().to_string();
+        match self.find_device(lc.device()) {
+            Some(dev) if dev.read_only() && !lc.read_only() => {
+                Some(ValidationError::LunReadOnlyDevice { object, device })
+            }
+            Some(_) => None,
+            None if devices.contains(&lc.device()) => None,
+            None => Some(ValidationError::NoDevice { object, device }),
+        }
+    }
+
+    /// like `from_cfg()`, but applies every object of `Config` on a best-effort basis. The
+    /// failed objects are skipped together with their children, and all failures are returned
+    /// at the end instead of aborting on the first one.
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Config, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let cfg = Config::read_file("/tmp/scst.yml")?;
+    ///     if let Err(errs) = scst.apply_cfg_besteffort(&cfg) {
+    ///         for e in errs {
+    ///             println!("{}", e);
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn apply_cfg_besteffort(
+        &mut self,
+        cfg: &Config,
+    ) -> std::result::Result<(), Vec<ApplyError>> {
+        let mut errs = Vec::new();
+        let mut collect = |object: String, e: anyhow::Error| {
+            errs.push(ApplyError::new(object, e));
+            Ok(())
+        };
+        // the collecting `fail` never stops the walks
+        let _ = self.apply_cfg_devices(cfg, &mut collect);
+        let _ = self.apply_cfg_drivers(cfg, &mut collect);
+
+        let root = self.copy_driver.root().to_path_buf();
+        if let Err(e) = self.copy_driver.load(root) {
+            errs.push(ApplyError::new(self.copy_driver.name(), e));
+        }
+
+        if !errs.is_empty() {
+            return Err(errs);
         }
 
         Ok(())
@@ -237,11 +1347,133 @@ impl Scst {
     pub fn to_cfg(&self) -> Config {
         Config::new(
             &self.handlers(),
-            &[self.iscsi()],
+            &self.drivers(),
             &self.copy_driver,
             self.version(),
         )
     }
+
+    /// converts the added and changed objects compared to the baseline to a minimal `Config`,
+    /// see `Config::delta()`.
+    pub fn to_cfg_delta(&self, baseline: &Config) -> Config {
+        self.to_cfg().delta(baseline)
+    }
+}
+
+impl Scst {
+    /// dumps the whole loaded scst tree to pretty JSON for bug reports. Unlike the serialization
+    /// of `Scst`, the dump includes the sysfs root paths of every object and the sessions of
+    /// targets.
+    pub fn dump_debug(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+
+        for handler in self.handlers.values() {
+            let hv = &mut value["handlers"][handler.name()];
+            debug_root(hv, handler.root());
+            for device in handler.devices() {
+                debug_root(&mut hv["devices"][device.name()], device.root());
+            }
+        }
+
+        for driver in self.drivers.values() {
+            let dv = &mut value["drivers"][driver.name()];
+            debug_root(dv, driver.root());
+            for target in driver.targets() {
+                debug_target(&mut dv["targets"][target.name()], target)?;
+            }
+        }
+
+        let cv = &mut value["copy_driver"];
+        debug_root(cv, self.copy_driver.root());
+        debug_target(&mut cv["tgt"], self.copy_driver.tgt())?;
+
+        for dgrp in self.device_groups.values() {
+            let gv = &mut value["device_groups"][dgrp.name()];
+            debug_root(gv, dgrp.root());
+            for tgrp in dgrp.target_groups() {
+                debug_root(&mut gv["target_groups"][tgrp.name()], tgrp.root());
+            }
+        }
+
+        let s = serde_json::to_string_pretty(&value)?;
+        Ok(s)
+    }
+}
+
+// SCST refuses a new rel_tgt_id on an enabled target, so it's disabled around the write and
+// enabled again even if the write fails.
+fn apply_rel_tgt_id(target: &mut Target, id: u64) -> Result<()> {
+    if !target.enabled() {
+        return target.set_rel_tgt_id(id);
+    }
+
+    target.disable()?;
+    let res = target.set_rel_tgt_id(id);
+    target.enable()?;
+    res
+}
+
+fn debug_root(value: &mut serde_json::Value, root: &Path) {
+    if let Some(map) = value.as_object_mut() {
+        map.insert(
+            "root".to_string(),
+            root.to_string_lossy().to_string().into(),
+        );
+    }
+}
+
+fn debug_target(value: &mut serde_json::Value, target: &Target) -> Result<()> {
+    debug_root(value, target.root());
+    for lun in target.luns() {
+        debug_root(&mut value["luns"][lun.name()], lun.root());
+    }
+    for group in target.ini_groups() {
+        let gv = &mut value["ini_groups"][group.name()];
+        debug_root(gv, group.root());
+        for lun in group.luns() {
+            debug_root(&mut gv["luns"][lun.name()], lun.root());
+        }
+    }
+
+    // the sessions directory is absent for some targets, like copy_manager_tgt.
+    let sessions = target
+        .sessions()
+        .unwrap_or_default()
+        .iter()
+        .map(|session| {
+            let mut sv = serde_json::to_value(session)?;
+            debug_root(&mut sv, session.root());
+            Ok(sv)
+        })
+        .collect::<Result<Vec<serde_json::Value>>>()?;
+    if let Some(map) = value.as_object_mut() {
+        map.insert("sessions".to_string(), sessions.into());
+    }
+
+    Ok(())
+}
+
+/// the kernel module providing the handler, like `scst_vdisk` for `vdisk_blockio`.
+fn handler_module(handler: &str) -> String {
+    if handler.starts_with("vdisk_") || handler == "vcdrom" {
+        return "scst_vdisk".to_string();
+    }
+
+    let name = handler.strip_prefix("dev_").unwrap_or(handler);
+    let name = name.strip_suffix("_perf").unwrap_or(name);
+    format!("scst_{}", name)
+}
+
+/// parses the leading `major.minor.patch` of a scst version like `3.7.0-pre`.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut nums = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse::<u32>());
+    let major = nums.next()?.ok()?;
+    let minor = nums.next().and_then(|n| n.ok()).unwrap_or(0);
+    let patch = nums.next().and_then(|n| n.ok()).unwrap_or(0);
+
+    Some((major, minor, patch))
 }
 
 impl Layer for Scst {
@@ -251,7 +1483,17 @@ impl Layer for Scst {
 
     fn load<P: AsRef<Path>>(&mut self, root: P) -> Result<()> {
         let root_ref = root.as_ref();
-        self.version = read_fl(root_ref.join("version"))?;
+        self.warnings.clear();
+
+        // nothing depends on the version, so a unreadable version file mustn't fail the load
+        self.version = match read_fl(root_ref.join("version")) {
+            Ok(version) => version,
+            Err(e) => {
+                self.warnings
+                    .push(format!("failed to read scst version: {}", e));
+                String::new()
+            }
+        };
 
         // traverse handler directory
         self.handlers = read_dir(root_ref.join(SCST_HANDLER))?
@@ -264,19 +1506,57 @@ impl Layer for Scst {
             })
             .collect();
 
-        // traverse driver directory
+        // traverse driver directory, iscsi is required and the other drivers are optional
         let mut iscsi_driver = Driver::default();
         iscsi_driver
-            .load(root_ref.join(SCST_DRIVER).join("iscsi"))
+            .load(root_ref.join(SCST_DRIVER).join(SCST_ISCSI))
             .map_err(|e| ScstError::Unknown(e))?;
-        self.iscsi_driver = iscsi_driver;
+
+        self.drivers = BTreeMap::new();
+        let entries = read_dir(root_ref.join(SCST_DRIVER))?
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter(|entry| {
+                entry.file_name() != SCST_ISCSI && entry.file_name() != SCST_COPY_MANAGER
+            });
+        for entry in entries {
+            let mut driver = Driver::default();
+            match driver.load(entry.path()) {
+                Ok(_) => {
+                    self.drivers.insert(driver.name().to_string(), driver);
+                }
+                Err(e) => self.warnings.push(format!(
+                    "failed to load driver {}: {}",
+                    entry.file_name().to_string_lossy(),
+                    e
+                )),
+            }
+        }
+        self.drivers.insert(SCST_ISCSI.to_string(), iscsi_driver);
+        for driver in self.drivers.values() {
+            self.warnings.extend_from_slice(driver.warnings());
+        }
 
         let mut copy_driver = CopyManager::default();
         copy_driver
-            .load(root_ref.join(SCST_DRIVER).join("copy_manager"))
+            .load(root_ref.join(SCST_DRIVER).join(SCST_COPY_MANAGER))
             .map_err(|e| ScstError::Unknown(e))?;
         self.copy_driver = copy_driver;
 
+        // traverse device group directory, it's absent without ALUA support
+        let dgrp_root = root_ref.join(SCST_DEVICE_GROUP);
+        if dgrp_root.exists() {
+            self.device_groups = read_dir(dgrp_root)?
+                .filter_map(|res| res.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| {
+                    let mut dgrp = DeviceGroup::default();
+                    dgrp.load(entry.path()).ok();
+                    (dgrp.name().to_string(), dgrp)
+                })
+                .collect();
+        }
+
         Ok(())
     }
 }
@@ -285,7 +1565,87 @@ impl Layer for Scst {
 mod test {
     use regex::Regex;
 
-    use super::Result;
+    use super::*;
+    use crate::testutil::{Fixture, echoed_cmds};
+
+    static CFG: &str = r#"
+handlers:
+  vdisk_blockio:
+    name: vdisk_blockio
+    devices:
+      vol:
+        name: vol
+        filename: /dev/zvol/tank/vol
+drivers:
+  iscsi:
+    name: iscsi
+    enabled: 1
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        enabled: 1
+"#;
+
+    fn load_scst(fx: &Fixture) -> Result<Scst> {
+        Scst::init_at(fx.root())
+    }
+
+    #[test]
+    fn iscsi_not_initialized() {
+        let mut scst = Scst::default();
+        assert!(scst.iscsi().targets().is_empty());
+        assert_eq!(scst.iscsi_mut().name(), "iscsi");
+        assert!(scst.iscsi_mut().enable().is_err());
+    }
+
+    #[test]
+    fn get_driver() -> Result<()> {
+        let fx = Fixture::new("get_driver");
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03");
+        let mut scst = load_scst(&fx)?;
+
+        let qla = scst.get_driver("qla2x00t")?;
+        assert_eq!(qla.name(), "qla2x00t");
+        assert!(qla.get_target("21:00:00:24:ff:01:02:03").is_ok());
+        assert!(
+            scst.get_driver("iscsi")?
+                .get_target("iqn.2018-11.com.vine:vol")
+                .is_ok()
+        );
+        assert_eq!(scst.iscsi().name(), "iscsi");
+        assert!(scst.get_driver_mut("qla2x00t").is_ok());
+
+        // a driver failing to load is skipped with a warning, and the warnings of the loaded
+        // drivers are kept
+        fx.file("targets/ib_srpt/enabled", "1\n")
+            .file("targets/scst_local/version", "3.7.0\n");
+        let scst = load_scst(&fx)?;
+        assert!(scst.get_driver("ib_srpt").is_err());
+        assert!(scst.get_driver("scst_local").is_ok());
+        let warnings = scst.warnings();
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("failed to load driver ib_srpt"))
+        );
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("enabled of driver scst_local"))
+        );
+
+        // copy_manager isn't a plain driver
+        let err = scst.get_driver("copy_manager").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::NoDriver(_))
+        ));
+
+        Ok(())
+    }
 
     #[test]
     fn it_works() -> Result<()> {
@@ -295,4 +1655,1036 @@ mod test {
         assert!(re.is_match("023:11:3:4"));
         Ok(())
     }
+
+    #[test]
+    fn from_cfg_devices_only() -> Result<()> {
+        let fx = Fixture::new("from_cfg_devices_only");
+        fx.scst().handler("vdisk_blockio", "0\n");
+        let mut scst = load_scst(&fx)?;
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol");
+
+        let cfg = Config::from_str(CFG)?;
+        scst.from_cfg_devices_only(&cfg)?;
+
+        assert_eq!(
+            echoed_cmds(),
+            vec!["add_device vol filename=/dev/zvol/tank/vol"]
+        );
+        assert!(scst.get_handler("vdisk_blockio")?.get_device("vol").is_ok());
+        assert!(scst.iscsi().targets().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_cfg_drivers_only() -> Result<()> {
+        let fx = Fixture::new("from_cfg_drivers_only");
+        fx.scst().handler("vdisk_blockio", "0\n");
+        let mut scst = load_scst(&fx)?;
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol");
+
+        let cfg = Config::from_str(CFG)?;
+        scst.from_cfg_drivers_only(&cfg)?;
+
+        let cmds = echoed_cmds();
+        assert!(cmds.iter().all(|cmd| !cmd.starts_with("add_device")));
+        assert!(cmds.contains(&"add_target iqn.2018-11.com.vine:vol".to_string()));
+        assert!(scst.get_handler("vdisk_blockio")?.devices().is_empty());
+        assert!(scst.iscsi().get_target("iqn.2018-11.com.vine:vol").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_multipath_consistency() -> Result<()> {
+        let fx = Fixture::new("check_multipath_consistency");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol")
+            .device("vdisk_blockio", "other", "/dev/zvol/tank/other")
+            .file("handlers/vdisk_blockio/vol1/usn", "a1b2\n")
+            .file("handlers/vdisk_blockio/vol2/usn", "c3d4\n")
+            .file("handlers/vdisk_blockio/vol1/t10_dev_id", "vol\n")
+            .file("handlers/vdisk_blockio/vol2/t10_dev_id", "vol\n");
+        let scst = load_scst(&fx)?;
+
+        let found = scst.check_multipath_consistency();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].filename(), "/dev/zvol/tank/vol");
+        assert_eq!(found[0].attribute(), "usn");
+        assert_eq!(
+            found[0].devices(),
+            &[
+                ("vol1".to_string(), "a1b2".to_string()),
+                ("vol2".to_string(), "c3d4".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_cfg_besteffort() -> Result<()> {
+        let fx = Fixture::new("apply_cfg_besteffort");
+        fx.scst().handler("vdisk_blockio", "0\n");
+        let mut scst = load_scst(&fx)?;
+        fx.device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .device("vdisk_blockio", "other", "/dev/zvol/tank/other")
+            .target("iscsi", "iqn.2018-11.com.vine:good")
+            .lun("targets/iscsi/iqn.2018-11.com.vine:good", 0, "other");
+
+        let cfg = Config::from_str(
+            r#"
+handlers:
+  vdisk_blockio:
+    name: vdisk_blockio
+    devices:
+      vol:
+        name: vol
+        filename: /dev/zvol/tank/vol
+drivers:
+  iscsi:
+    name: iscsi
+    enabled: 1
+    targets:
+      iqn.2018-11.com.vine:bad:
+        name: iqn.2018-11.com.vine:bad
+      iqn.2018-11.com.vine:good:
+        name: iqn.2018-11.com.vine:good
+        luns:
+          - id: 0
+            device: vol
+"#,
+        )?;
+        let errs = scst.apply_cfg_besteffort(&cfg).unwrap_err();
+
+        assert_eq!(errs.len(), 2);
+        assert_eq!(errs[0].object(), "iscsi/iqn.2018-11.com.vine:bad");
+        // the LUN mapping another device isn't left as is silently
+        assert_eq!(errs[1].object(), "iscsi/iqn.2018-11.com.vine:good/lun0");
+        assert!(scst.get_handler("vdisk_blockio")?.get_device("vol").is_ok());
+        assert!(scst.iscsi().get_target("iqn.2018-11.com.vine:good").is_ok());
+        assert!(scst.iscsi().get_target("iqn.2018-11.com.vine:bad").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn devices_for_filename() -> Result<()> {
+        let fx = Fixture::new("devices_for_filename");
+        let disk = fx.path("disk.img");
+        let alias = fx.path("alias.img");
+        fx.scst()
+            .file("disk.img", "")
+            .link("alias.img", &disk)
+            .device("vdisk_blockio", "vol1", &disk.to_string_lossy())
+            .device("vdisk_fileio", "vol2", &alias.to_string_lossy())
+            .device("vdisk_fileio", "other", "/dev/zvol/tank/other");
+        let scst = load_scst(&fx)?;
+
+        let found = scst
+            .devices_for_filename(&disk)
+            .iter()
+            .map(|(h, dev)| (h.name(), dev.name()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            found,
+            vec![("vdisk_blockio", "vol1"), ("vdisk_fileio", "vol2")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_cfg_group_lun_read_only() -> Result<()> {
+        let fx = Fixture::new("from_cfg_group_lun_read_only");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "vol", &[]);
+        let mut scst = load_scst(&fx)?;
+        fx.lun(&format!("{}/ini_groups/vol", tgt), 0, "vol");
+
+        let cfg = Config::from_str(
+            r#"
+drivers:
+  iscsi:
+    name: iscsi
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        enabled: 0
+        groups:
+          vol:
+            name: vol
+            luns:
+            - id: 0
+              device: vol
+              read_only: 1
+"#,
+        )?;
+        scst.from_cfg_drivers_only(&cfg)?;
+
+        assert_eq!(echoed_cmds(), vec!["add vol 0 read_only=1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dump_debug() -> Result<()> {
+        let fx = Fixture::new("dump_debug");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol")
+            .session(tgt, "iqn.1988-12.com.oracle:a", "1");
+        let scst = load_scst(&fx)?;
+
+        let dump = scst.dump_debug()?;
+        let value = serde_json::from_str::<serde_json::Value>(&dump)?;
+
+        let device = &value["handlers"]["vdisk_blockio"]["devices"]["vol"];
+        assert_eq!(device["filename"], "/dev/zvol/tank/vol");
+        assert_eq!(
+            device["root"],
+            fx.path("handlers/vdisk_blockio/vol")
+                .to_string_lossy()
+                .as_ref()
+        );
+        let target = &value["drivers"]["iscsi"]["targets"]["iqn.2018-11.com.vine:vol"];
+        assert_eq!(
+            target["luns"]["lun0"]["root"],
+            fx.path(format!("{}/luns/0", tgt))
+                .to_string_lossy()
+                .as_ref()
+        );
+        assert_eq!(target["sessions"][0]["sid"], "1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn init_from_env() -> Result<()> {
+        let fx = Fixture::new("init_from_env");
+        fx.scst()
+            .device("vdisk_blockio", "vol", "/dev/zvol/tank/vol");
+
+        std::env::set_var(SCST_ROOT_ENV, fx.root());
+        let scst = Scst::init();
+        std::env::remove_var(SCST_ROOT_ENV);
+        let scst = scst?;
+
+        assert_eq!(scst.root(), fx.root());
+        assert_eq!(scst.version(), "3.7.0");
+        assert!(scst.get_handler("vdisk_blockio")?.get_device("vol").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn init_without_version() -> Result<()> {
+        let fx = Fixture::new("init_without_version");
+        fx.scst().remove("version");
+
+        let scst = Scst::init_at(fx.root())?;
+        assert_eq!(scst.version(), "");
+        assert_eq!(scst.warnings().len(), 1);
+        assert!(scst.warnings()[0].contains("version"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_device() -> Result<()> {
+        let fx = Fixture::new("rename_device");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        let fc = "targets/qla2x00t/21:00:00:24:ff:01:02:03";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .file("handlers/vdisk_blockio/vol1/nv_cache", "1\n")
+            .file("handlers/vdisk_blockio/vol1/t10_dev_id", "vol1-id\n")
+            .file("handlers/vdisk_blockio/vol1/usn", "abc123\n")
+            .device("vdisk_blockio", "other", "/dev/zvol/tank/other")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .file(format!("{}/luns/0/alias", tgt), "database\n")
+            .lun(tgt, 1, "other")
+            .group(tgt, "grp", &[])
+            .lun(&grp, 3, "vol1")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03")
+            .lun(fc, 0, "vol1");
+        let mut scst = load_scst(&fx)?;
+
+        // the kernel creates the new device and the restored LUNs
+        fx.device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol1")
+            .file("handlers/vdisk_blockio/vol2/t10_dev_id", "2d2d2d2d\n")
+            .file("handlers/vdisk_blockio/vol2/usn", "5e6f7a8b\n")
+            .lun(tgt, 0, "vol2")
+            .lun(&grp, 3, "vol2")
+            .lun(fc, 0, "vol2");
+
+        let err = scst
+            .rename_device("vdisk_blockio", "vol1", "other")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceExists(_))
+        ));
+
+        scst.rename_device("vdisk_blockio", "vol1", "vol2")?;
+        assert_eq!(
+            echoed_cmds(),
+            vec![
+                "del 0",
+                "del 3",
+                "del 0",
+                "del_device vol1",
+                "add_device vol2 filename=/dev/zvol/tank/vol1 \
+                 active=1;blocksize=512;nv_cache=1;read_only=0",
+                "vol1-id",
+                "abc123",
+                "add vol2 0",
+                "database",
+                "add vol2 3",
+                "add vol2 0",
+            ]
+        );
+
+        let handler = scst.get_handler("vdisk_blockio")?;
+        assert!(handler.get_device("vol1").is_err());
+        let device = handler.get_device("vol2")?;
+        assert_eq!(device.t10_dev_id(), "vol1-id");
+        assert_eq!(device.usn(), "abc123");
+
+        let target = scst.iscsi().get_target("iqn.2018-11.com.vine:vol")?;
+        assert_eq!(target.get_lun("lun0")?.device(), "vol2");
+        assert_eq!(target.get_lun("lun0")?.alias(), Some("database"));
+        assert_eq!(target.get_lun("lun1")?.device(), "other");
+        let group = target.get_ini_group("grp")?;
+        assert_eq!(group.get_lun("lun3")?.device(), "vol2");
+        let target = scst
+            .get_driver("qla2x00t")?
+            .get_target("21:00:00:24:ff:01:02:03")?;
+        assert_eq!(target.get_lun("lun0")?.device(), "vol2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_device_rollback() -> Result<()> {
+        let fx = Fixture::new("rename_device_rollback");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .file(format!("{}/luns/0/read_only", tgt), "1\n")
+            .dir("handlers/vdisk_blockio/mgmt")
+            .simulate();
+        let mut scst = load_scst(&fx)?;
+
+        // the device can't be deleted, the LUN is restored
+        assert!(scst.rename_device("vdisk_blockio", "vol1", "vol2").is_err());
+        assert_eq!(
+            echoed_cmds(),
+            vec!["del 0", "del_device vol1", "add vol1 0 read_only=1"]
+        );
+        let lun = scst
+            .iscsi()
+            .get_target("iqn.2018-11.com.vine:vol")?
+            .get_lun("lun0")?;
+        assert_eq!(lun.device(), "vol1");
+        assert!(lun.read_only());
+        assert!(scst.find_device("vol1").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn supports_attribute() -> Result<()> {
+        let fx = Fixture::new("supports_attribute");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .file("handlers/vdisk_blockio/vol1/cluster_mode", "0\n");
+        let scst = load_scst(&fx)?;
+
+        let dev = "handlers/vdisk_blockio/vol1";
+        assert!(scst.supports_attribute(dev, "cluster_mode"));
+        assert!(scst.supports_attribute(dev, "blocksize"));
+        assert!(!scst.supports_attribute(dev, "dif_mode"));
+        assert!(!scst.supports_attribute(dev, "no_such_attr"));
+
+        // not created yet, only the version is checked
+        let dev = "handlers/vdisk_blockio/vol2";
+        assert!(scst.supports_attribute(dev, "dif_mode"));
+        assert!(!scst.supports_attribute(dev, "no_such_attr"));
+
+        fx.file("version", "3.1.0-pre1\n");
+        let scst = load_scst(&fx)?;
+        assert!(!scst.supports_attribute("handlers/vdisk_blockio/vol1", "cluster_mode"));
+        assert!(scst.supports_attribute("handlers/vdisk_blockio/vol2", "dif_mode"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("3.7.0"), Some((3, 7, 0)));
+        assert_eq!(parse_version("3.8.0-pre"), Some((3, 8, 0)));
+        assert_eq!(parse_version("3.1"), Some((3, 1, 0)));
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn devices_of_type() -> Result<()> {
+        let fx = Fixture::new("devices_of_type");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .device("vdisk_fileio", "file1", "/tank/file1.img");
+        let scst = load_scst(&fx)?;
+
+        let names = scst
+            .devices_of_type(&HandlerType::Blockio)
+            .into_iter()
+            .map(|(h, dev)| format!("{}/{}", h.name(), dev.name()))
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["vdisk_blockio/vol1", "vdisk_blockio/vol2"]);
+
+        assert_eq!(scst.devices_of_type(&HandlerType::Fileio).len(), 1);
+        assert!(scst.devices_of_type(&HandlerType::Nullio).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_device_unloaded_handler() -> Result<()> {
+        let fx = Fixture::new("add_device_unloaded_handler");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .handler("dev_disk", "0 - Direct-access device (e.g., disk)\n");
+        let mut scst = load_scst(&fx)?;
+        assert_eq!(scst.loaded_handlers(), vec!["dev_disk", "vdisk_blockio"]);
+
+        let err = scst
+            .add_device("vdisk_fileio", "file1", "/tank/file1.img", &Options::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::NoHandler(h)) if h == "vdisk_fileio"
+        ));
+        assert!(err.to_string().contains("modprobe scst_vdisk"));
+        assert!(echoed_cmds().is_empty());
+
+        assert_eq!(handler_module("dev_disk_perf"), "scst_disk");
+        assert_eq!(handler_module("dev_changer"), "scst_changer");
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_cfg() -> Result<()> {
+        let fx = Fixture::new("validate_cfg");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "ro", "/dev/zvol/tank/ro")
+            .file("handlers/vdisk_blockio/ro/read_only", "1\n");
+        let scst = load_scst(&fx)?;
+
+        let cfg = Config::from_str(
+            r#"
+handlers:
+  vdisk_blockio:
+    name: vdisk_blockio
+    devices:
+      vol2:
+        name: vol2
+        filename: /dev/zvol/tank/vol2
+  vdisk_fileio:
+    name: vdisk_fileio
+    devices: {}
+drivers:
+  iscsi:
+    name: iscsi
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        luns:
+          - id: 0
+            device: vol1
+          - id: 1
+            device: vol2
+          - id: 2
+            device: ro
+        groups:
+          grp:
+            name: grp
+            luns:
+              - id: 0
+                device: missing
+  qla2x00t:
+    name: qla2x00t
+"#,
+        )?;
+
+        let errs = scst.validate_cfg(&cfg).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![
+                ValidationError::NoHandler("vdisk_fileio".to_string()),
+                ValidationError::LunReadOnlyDevice {
+                    object: "iscsi/iqn.2018-11.com.vine:vol/lun2".to_string(),
+                    device: "ro".to_string(),
+                },
+                ValidationError::NoDevice {
+                    object: "iscsi/iqn.2018-11.com.vine:vol/grp/lun0".to_string(),
+                    device: "missing".to_string(),
+                },
+                ValidationError::NoDriver("qla2x00t".to_string()),
+            ]
+        );
+        assert!(echoed_cmds().is_empty());
+
+        scst.validate_cfg(&Config::from_str(CFG)?)
+            .map_err(|errs| anyhow::anyhow!("{:?}", errs))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_cfg_attributes() -> Result<()> {
+        let fx = Fixture::new("validate_cfg_attributes");
+        fx.scst()
+            .handler("vdisk_nullio", "0 - virtual\n")
+            .file(
+                "handlers/vdisk_nullio/mgmt",
+                "Usage: echo \"add_device device_name [parameters]\" >mgmt\n\
+                 The following parameters available: blocksize, read_only, size\n",
+            )
+            .handler("vdisk_fileio", "0 - virtual\n")
+            .file(
+                "handlers/vdisk_fileio/mgmt",
+                "Usage: echo \"add_device device_name [parameters]\" >mgmt\n\
+                 The following parameters available: filename, read_only\n",
+            )
+            .file("targets/ib_srpt/version", "3.7.0\n");
+        let scst = load_scst(&fx)?;
+
+        let cfg = Config::from_str(
+            r#"
+handlers:
+  vdisk_nullio:
+    name: vdisk_nullio
+    devices:
+      null1:
+        name: null1
+        filename: /var/lib/scst/null1.img
+  vdisk_fileio:
+    name: vdisk_fileio
+    devices:
+      img1:
+        name: img1
+        filename: /var/lib/scst/img1.img
+drivers:
+  ib_srpt:
+    name: ib_srpt
+    enabled: 1
+"#,
+        )?;
+
+        let errs = scst.validate_cfg(&cfg).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![
+                ValidationError::UnsupportedParam {
+                    object: "vdisk_nullio/null1".to_string(),
+                    param: "filename".to_string(),
+                },
+                ValidationError::UnsupportedAttr {
+                    object: "ib_srpt".to_string(),
+                    attr: "enabled".to_string(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn expose() -> Result<()> {
+        let fx = Fixture::new("expose");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .simulate();
+        let mut scst = load_scst(&fx)?;
+
+        let spec = ExposeSpec {
+            target: "iqn.2018-11.com.vine:vol".to_string(),
+            device: "vol1".to_string(),
+            initiator: "iqn.1991-05.com.microsoft:host".to_string(),
+            lun_id: 0,
+            read_only: true,
+        };
+        scst.expose("iscsi", spec.clone())?;
+        assert_eq!(
+            echoed_cmds(),
+            vec![
+                "add_target iqn.2018-11.com.vine:vol",
+                "create iqn.1991-05.com.microsoft:host",
+                "add vol1 0 read_only=1",
+                "add iqn.1991-05.com.microsoft:host",
+                "1",
+            ]
+        );
+
+        let mut scst = load_scst(&fx)?;
+        let target = scst.iscsi().get_target("iqn.2018-11.com.vine:vol")?;
+        assert!(target.enabled());
+        assert!(target.luns().is_empty());
+        let group = target.get_ini_group("iqn.1991-05.com.microsoft:host")?;
+        assert_eq!(group.initiators(), &["iqn.1991-05.com.microsoft:host"]);
+        let lun = group.get_lun("lun0")?;
+        assert_eq!(lun.device(), "vol1");
+        assert!(lun.read_only());
+
+        scst.expose("iscsi", spec.clone())?;
+        assert!(echoed_cmds().is_empty());
+
+        let err = scst
+            .expose(
+                "iscsi",
+                ExposeSpec {
+                    device: "vol2".to_string(),
+                    ..spec
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::NoDevice(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unexpose() -> Result<()> {
+        let fx = Fixture::new("unexpose");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .simulate();
+        let mut scst = load_scst(&fx)?;
+
+        let tgt = "iqn.2018-11.com.vine:vol";
+        for initiator in &["iqn.1991-05.com.microsoft:a", "iqn.1991-05.com.microsoft:b"] {
+            scst.expose(
+                "iscsi",
+                ExposeSpec {
+                    target: tgt.to_string(),
+                    device: "vol1".to_string(),
+                    initiator: initiator.to_string(),
+                    lun_id: 0,
+                    read_only: false,
+                },
+            )?;
+        }
+        echoed_cmds();
+
+        scst.unexpose("iscsi", tgt, "vol1", "iqn.1991-05.com.microsoft:a")?;
+        assert_eq!(
+            echoed_cmds(),
+            vec![
+                "del iqn.1991-05.com.microsoft:a",
+                "del 0",
+                "del iqn.1991-05.com.microsoft:a",
+            ]
+        );
+
+        let mut scst = load_scst(&fx)?;
+        let target = scst.iscsi().get_target(tgt)?;
+        assert!(target.enabled());
+        assert!(target.get_ini_group("iqn.1991-05.com.microsoft:a").is_err());
+        let group = target.get_ini_group("iqn.1991-05.com.microsoft:b")?;
+        assert_eq!(group.initiators(), &["iqn.1991-05.com.microsoft:b"]);
+        assert_eq!(group.get_lun("lun0")?.device(), "vol1");
+        assert!(scst.find_device("vol1").is_some());
+
+        scst.unexpose("iscsi", tgt, "vol1", "iqn.1991-05.com.microsoft:a")?;
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn select_root_precedence() {
+        let fx = Fixture::new("select_root_precedence");
+        let legacy = fx.root().join("kernel/scst_tgt");
+        let modern = fx.root().join("devices/scst");
+
+        fs::create_dir_all(&modern).unwrap();
+        assert_eq!(select_root(&legacy, &modern), modern.as_path());
+
+        fs::create_dir_all(&legacy).unwrap();
+        assert_eq!(select_root(&legacy, &modern), legacy.as_path());
+    }
+
+    #[test]
+    fn copy_manager() -> Result<()> {
+        let fx = Fixture::new("scst_copy_manager");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .lun("targets/copy_manager/copy_manager_tgt", 0, "vol1");
+        let scst = load_scst(&fx)?;
+
+        let copy_manager = scst.copy_manager();
+        assert_eq!(copy_manager.name(), "copy_manager");
+        assert_eq!(copy_manager.get_lun("lun0")?.device(), "vol1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn all_sessions() -> Result<()> {
+        let fx = Fixture::new("all_sessions");
+        let tgt1 = "targets/iscsi/iqn.2018-11.com.vine:vol1";
+        let tgt2 = "targets/iscsi/iqn.2018-11.com.vine:vol2";
+        let tgt3 = "targets/iscsi/iqn.2018-11.com.vine:vol3";
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol3")
+            .session(tgt1, "iqn.host1", "0x1")
+            .session(tgt2, "iqn.host2", "0x2")
+            .session(tgt2, "iqn.host3", "0x3");
+        let scst = load_scst(&fx)?;
+        fx.remove(format!("{}/sessions", tgt3));
+
+        let all = scst.all_sessions()?;
+        assert_eq!(all.warnings().len(), 1);
+        assert!(all.warnings()[0].contains("vol3"));
+        assert!(scst.warnings().is_empty());
+
+        let sessions = all
+            .into_sessions()
+            .into_iter()
+            .map(|(driver, target, session)| (driver, target, session.sid().to_string()))
+            .collect::<Vec<(String, String, String)>>();
+        let expect = [
+            ("iscsi", "iqn.2018-11.com.vine:vol1", "0x1"),
+            ("iscsi", "iqn.2018-11.com.vine:vol2", "0x2"),
+            ("iscsi", "iqn.2018-11.com.vine:vol2", "0x3"),
+        ]
+        .iter()
+        .map(|(d, t, s)| (d.to_string(), t.to_string(), s.to_string()))
+        .collect::<Vec<(String, String, String)>>();
+        assert_eq!(sessions, expect);
+
+        Ok(())
+    }
+
+    #[test]
+    fn to_cfg_drivers() -> Result<()> {
+        let fx = Fixture::new("to_cfg_drivers");
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03");
+        let scst = load_scst(&fx)?;
+        assert_eq!(
+            scst.drivers()
+                .iter()
+                .map(|driver| driver.name())
+                .collect::<Vec<&str>>(),
+            vec!["iscsi", "qla2x00t"]
+        );
+
+        let yml = scst.to_cfg().to_yml()?;
+        let cfg = Config::from_str(&yml)?;
+        assert_eq!(cfg.to_yml()?, yml);
+        assert_eq!(
+            cfg.drivers()
+                .iter()
+                .map(|dc| dc.name())
+                .collect::<Vec<&str>>(),
+            vec!["copy_manager", "iscsi", "qla2x00t"]
+        );
+
+        // the config recreates the targets of both drivers
+        let fx = Fixture::new("to_cfg_drivers_apply");
+        fx.scst().driver("qla2x00t").simulate();
+        let mut fresh = load_scst(&fx)?;
+        fresh.from_cfg_drivers_only(&cfg)?;
+        assert!(fresh.iscsi().get_target("iqn.2018-11.com.vine:vol").is_ok());
+        assert!(
+            fresh
+                .get_driver("qla2x00t")?
+                .get_target("21:00:00:24:ff:01:02:03")
+                .is_ok()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn to_cfg_delta() -> Result<()> {
+        let fx = Fixture::new("to_cfg_delta");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol2");
+        let baseline = load_scst(&fx)?.to_cfg();
+
+        fx.lun("targets/iscsi/iqn.2018-11.com.vine:vol2", 0, "vol1");
+        let scst = load_scst(&fx)?;
+
+        let delta = scst.to_cfg_delta(&baseline);
+        assert!(delta.handlers().is_empty());
+        assert_eq!(delta.drivers().len(), 1);
+        let targets = delta.drivers()[0].targets();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].name(), "iqn.2018-11.com.vine:vol2");
+        assert_eq!(targets[0].luns()[0].device(), "vol1");
+
+        assert!(scst.to_cfg_delta(&scst.to_cfg()).drivers().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn drain() -> Result<()> {
+        let fx = Fixture::new("drain");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .target("iscsi", "iqn.2018-11.com.vine:off")
+            .file("targets/iscsi/iqn.2018-11.com.vine:off/enabled", "0\n")
+            .session(tgt, "iqn.1991-05.com.microsoft:a", "1")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03");
+        let mut scst = load_scst(&fx)?;
+
+        let session = fx.path(format!("{}/sessions/iqn.1991-05.com.microsoft:a", tgt));
+        let logout = thread::spawn(move || {
+            thread::sleep(DRAIN_POLL_INTERVAL / 2);
+            fs::remove_dir_all(session).unwrap();
+        });
+        let report = scst.drain(Duration::from_secs(5))?;
+        logout.join().unwrap();
+
+        assert!(report.is_drained());
+        assert_eq!(
+            report.disabled(),
+            &["iqn.2018-11.com.vine:vol", "21:00:00:24:ff:01:02:03"]
+        );
+        assert_eq!(echoed_cmds(), vec!["0"; 4]);
+        assert!(!scst.iscsi().enabled());
+        assert!(!scst.get_driver("qla2x00t")?.enabled());
+        assert!(
+            !scst
+                .iscsi()
+                .get_target("iqn.2018-11.com.vine:vol")?
+                .enabled()
+        );
+
+        // a session left is reported after the timeout
+        fx.session(tgt, "iqn.1991-05.com.microsoft:b", "2");
+        let report = scst.drain(Duration::from_millis(10))?;
+        assert!(!report.is_drained());
+        assert_eq!(report.undrained()[0].0, "iqn.2018-11.com.vine:vol");
+
+        Ok(())
+    }
+
+    #[test]
+    fn del_device_in_use() -> Result<()> {
+        let fx = Fixture::new("del_device_in_use");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[])
+            .lun(&format!("{}/ini_groups/grp", tgt), 3, "vol1")
+            .device("vdisk_blockio", "vol3", "/dev/zvol/tank/vol3")
+            .driver("qla2x00t")
+            .target("qla2x00t", "21:00:00:24:ff:01:02:03")
+            .lun("targets/qla2x00t/21:00:00:24:ff:01:02:03", 0, "vol3");
+        let mut scst = load_scst(&fx)?;
+
+        let vol1 = scst.get_handler("vdisk_blockio")?.get_device("vol1")?;
+        assert!(vol1.is_in_use(&scst));
+        let vol2 = scst.get_handler("vdisk_blockio")?.get_device("vol2")?;
+        assert!(!vol2.is_in_use(&scst));
+
+        let err = scst.del_device("vdisk_blockio", "vol1").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceInUse { device, lun })
+                if device == "vol1" && lun == "iscsi/iqn.2018-11.com.vine:vol/grp/lun3"
+        ));
+        // mapped through another driver
+        let err = scst.del_device("vdisk_blockio", "vol3").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DeviceInUse { lun, .. })
+                if lun == "qla2x00t/21:00:00:24:ff:01:02:03/lun0"
+        ));
+        assert!(echoed_cmds().is_empty());
+
+        scst.del_device("vdisk_blockio", "vol2")?;
+        assert_eq!(echoed_cmds(), vec!["del_device vol2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn trace_level() -> Result<()> {
+        let fx = Fixture::new("trace_level");
+        fx.scst().file(
+            "trace_level",
+            "out_of_mem | minor | pid | mgmt\n\n\nUsage:\n\techo \"all|none|default\" >trace_level\n",
+        );
+        let mut scst = load_scst(&fx)?;
+        assert_eq!(scst.trace_level()?, 0x1043);
+
+        scst.set_trace_level(0x1003)?;
+        assert_eq!(echoed_cmds(), vec!["value 0x1003"]);
+
+        fx.file("trace_level", "0x1003\n");
+        assert_eq!(scst.trace_level()?, 0x1003);
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_cfg_rel_tgt_id() -> Result<()> {
+        let fx = Fixture::new("from_cfg_rel_tgt_id");
+        fx.scst().target("iscsi", "iqn.2018-11.com.vine:vol");
+        let mut scst = load_scst(&fx)?;
+
+        let cfg = Config::from_str(
+            r#"
+drivers:
+  iscsi:
+    enabled: 0
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        enabled: 0
+        rel_tgt_id: 7
+"#,
+        )?;
+        scst.from_cfg_drivers_only(&cfg)?;
+        // the enabled target is disabled around the write
+        assert_eq!(echoed_cmds(), vec!["0", "7", "1"]);
+        assert_eq!(
+            scst.iscsi()
+                .get_target("iqn.2018-11.com.vine:vol")?
+                .rel_tgt_id(),
+            7
+        );
+
+        // applied again, the id is kept
+        scst.from_cfg_drivers_only(&cfg)?;
+        assert!(echoed_cmds().is_empty());
+
+        // a refused id leaves the target enabled
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.remove(format!("{}/rel_tgt_id", tgt))
+            .dir(format!("{}/rel_tgt_id", tgt));
+        let cfg = Config::from_str(
+            r#"
+drivers:
+  iscsi:
+    enabled: 0
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        enabled: 0
+        rel_tgt_id: 8
+"#,
+        )?;
+        assert!(scst.from_cfg_drivers_only(&cfg).is_err());
+        assert_eq!(echoed_cmds(), vec!["0", "8", "1"]);
+        assert!(
+            scst.iscsi()
+                .get_target("iqn.2018-11.com.vine:vol")?
+                .enabled()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_cfg_idempotent() -> Result<()> {
+        let fx = Fixture::new("from_cfg_idempotent");
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .simulate();
+        let mut scst = load_scst(&fx)?;
+
+        let cfg = Config::from_str(
+            r#"
+handlers:
+  vdisk_blockio:
+    name: vdisk_blockio
+    devices:
+      vol1:
+        name: vol1
+        filename: /dev/zvol/tank/vol1
+drivers:
+  iscsi:
+    name: iscsi
+    enabled: 0
+    targets:
+      iqn.2018-11.com.vine:vol:
+        name: iqn.2018-11.com.vine:vol
+        enabled: 0
+        luns:
+          - id: 0
+            device: vol1
+        groups:
+          grp:
+            name: grp
+            luns:
+              - id: 1
+                device: vol1
+            initiators:
+              - iqn.1988-12.com.oracle:d4ebaa45254
+"#,
+        )?;
+        scst.from_cfg(&cfg)?;
+        let cmds = echoed_cmds();
+        assert!(cmds.contains(&"add vol1 0".to_string()));
+        assert!(cmds.contains(&"add vol1 1".to_string()));
+
+        // applied again, the existing LUNs aren't added twice
+        scst.from_cfg(&cfg)?;
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_info() -> Result<()> {
+        let fx = Fixture::new("build_info");
+        fx.scst()
+            .file("version", "3.7.0\nEXTRACHECKS\nDEBUG\n")
+            .file("setup_id", "0x0\n");
+        let scst = load_scst(&fx)?;
+
+        let info = scst.build_info()?;
+        assert_eq!(info.version(), Some("3.7.0"));
+        assert_eq!(info.build_flags(), &["EXTRACHECKS", "DEBUG"]);
+        assert_eq!(info.setup_id(), Some("0x0"));
+        assert_eq!(info.last_sysfs_mgmt_res(), None);
+
+        fx.file("last_sysfs_mgmt_res", "-22\n");
+        assert_eq!(scst.build_info()?.last_sysfs_mgmt_res(), Some(-22));
+
+        Ok(())
+    }
 }
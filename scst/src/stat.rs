@@ -1,10 +1,17 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "parallel")]
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::{Layer, read_dir, read_fl};
+use crate::{Layer, ScstError, echo, parse_ip, read_dir, read_fl};
+
+/// the most threads loading sessions concurrently.
+#[cfg(feature = "parallel")]
+static SESSION_LOAD_THREADS: usize = 8;
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct IOStat {
     bidi_cmd_count: usize,
@@ -20,17 +27,29 @@ pub struct IOStat {
     read_unaligned_cmd_count: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 pub struct Session {
     #[serde(skip)]
     root: String,
     sid: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     thread_pid: String,
     initiator_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    login_time: Option<u64>,
 
     ips: Vec<SessionIP>,
 }
 
+/// the view of a session for an API, without the volatile and internal fields like
+/// `thread_pid`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicSession<'a> {
+    sid: &'a str,
+    initiator_name: &'a str,
+    ips: &'a [SessionIP],
+}
+
 impl Session {
     pub fn sid(&self) -> &str {
         &self.sid
@@ -48,9 +67,38 @@ impl Session {
         self.ips.iter().collect()
     }
 
+    /// how long the session is connected, told by the `login_time` attribute in seconds
+    /// since the epoch. None if the driver doesn't report it.
+    pub fn connected_duration(&self) -> Option<Duration> {
+        let login = UNIX_EPOCH + Duration::from_secs(self.login_time?);
+        SystemTime::now().duration_since(login).ok()
+    }
+
+    /// closes the session by its `force_close` attribute.
+    pub(crate) fn force_close(&self) -> Result<()> {
+        echo(self.root().join("force_close"), "1".into())
+            .map_err(|e| e.context(ScstError::SessionCloseFail))?;
+
+        Ok(())
+    }
+
+    /// the commands of the session in flight, read from the `active_commands` attribute.
+    pub fn active_commands(&self) -> Result<u64> {
+        Ok(read_fl(self.root().join("active_commands"))?.parse::<u64>()?)
+    }
+
     pub fn io_stat(&self) -> Result<IOStat> {
         read_stat(self.root())
     }
+
+    /// the view to serialize for an API, keeping sid, initiator_name and the connections.
+    pub fn public(&self) -> PublicSession<'_> {
+        PublicSession {
+            sid: &self.sid,
+            initiator_name: &self.initiator_name,
+            ips: &self.ips,
+        }
+    }
 }
 
 impl Layer for Session {
@@ -64,12 +112,14 @@ impl Layer for Session {
         self.sid = read_fl(root_ref.join("sid"))?;
         self.thread_pid = read_fl(root_ref.join("thread_pid"))?;
         self.initiator_name = read_fl(root_ref.join("initiator_name"))?;
+        self.login_time = read_fl(root_ref.join("login_time"))
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok());
 
-        let ip_re = Regex::new(r"^(?:\d{1,3}\.){3}\d{1,3}$")?;
         self.ips = read_dir(root_ref)?
             .filter_map(|res| res.ok())
             .filter(|entry| {
-                entry.path().is_dir() && ip_re.is_match(&entry.file_name().to_string_lossy())
+                entry.path().is_dir() && is_conn_dir(&entry.file_name().to_string_lossy())
             })
             .filter_map(|entry| {
                 let mut ip = SessionIP::default();
@@ -82,7 +132,54 @@ impl Layer for Session {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// loads the sessions in the given directories, sorted by sid. With the `parallel` feature
+/// they are loaded concurrently by a bounded number of threads.
+pub(crate) fn load_sessions(paths: &[PathBuf]) -> Vec<Session> {
+    #[cfg(feature = "parallel")]
+    return load_sessions_parallel(paths, SESSION_LOAD_THREADS);
+
+    #[cfg(not(feature = "parallel"))]
+    load_sessions_serial(paths)
+}
+
+fn load_sessions_serial(paths: &[PathBuf]) -> Vec<Session> {
+    let mut sessions = paths
+        .iter()
+        .map(|path| {
+            let mut session = Session::default();
+            session.load(path).ok();
+            session
+        })
+        .collect::<Vec<Session>>();
+    sessions.sort_by(|a, b| a.sid.cmp(&b.sid));
+
+    sessions
+}
+
+#[cfg(feature = "parallel")]
+fn load_sessions_parallel(paths: &[PathBuf], threads: usize) -> Vec<Session> {
+    let chunk_size = ((paths.len() + threads - 1) / threads.max(1)).max(1);
+    let mut sessions = thread::scope(|s| {
+        paths
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || load_sessions_serial(chunk)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect::<Vec<Session>>()
+    });
+    sessions.sort_by(|a, b| a.sid.cmp(&b.sid));
+
+    sessions
+}
+
+/// checks the session subdirectory is a connection, which is named by the initiator IPv4 or
+/// IPv6 address.
+fn is_conn_dir(name: &str) -> bool {
+    parse_ip(name).is_some()
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 pub struct SessionIP {
     #[serde(skip)]
     root: String,
@@ -156,3 +253,102 @@ pub fn read_stat<S: AsRef<Path>>(root: S) -> Result<IOStat> {
 
     Ok(stat)
 }
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::testutil::Fixture;
+
+    #[test]
+    fn load_session_ips() -> Result<()> {
+        let fx = Fixture::new("load_session_ips");
+        let sess = "targets/iscsi/iqn.2018-11.com.vine:vol/sessions/iqn.1988-12.com.oracle:a";
+        fx.session(
+            "targets/iscsi/iqn.2018-11.com.vine:vol",
+            "iqn.1988-12.com.oracle:a",
+            "1",
+        )
+        .dir(format!("{}/luns", sess));
+        for (dir, ip) in [
+            ("192.168.2.30", "192.168.2.30"),
+            ("fe80::1", "fe80::1"),
+            ("[fe80::2]", "fe80::2"),
+        ] {
+            fx.file(format!("{}/{}/cid", sess, dir), "0\n")
+                .file(format!("{}/{}/ip", sess, dir), &format!("{}\n", ip))
+                .file(format!("{}/{}/state", sess, dir), "established\n")
+                .file(format!("{}/{}/target_ip", sess, dir), "192.168.2.1\n");
+        }
+
+        let mut session = Session::default();
+        session.load(fx.path(sess))?;
+
+        let mut ips = session
+            .ips()
+            .iter()
+            .map(|ip| ip.ip())
+            .collect::<Vec<&str>>();
+        ips.sort();
+        assert_eq!(ips, vec!["192.168.2.30", "fe80::1", "fe80::2"]);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn load_sessions_parallel_as_serial() -> Result<()> {
+        let fx = Fixture::new("load_sessions_parallel");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        for i in 0..100 {
+            fx.session(
+                tgt,
+                &format!("iqn.1988-12.com.oracle:{}", i),
+                &format!("{:x}", 0x10000 + (i * 7919) % 100),
+            );
+        }
+
+        let paths = read_dir(fx.path(format!("{}/sessions", tgt)))?
+            .filter_map(|res| res.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>();
+
+        let serial = load_sessions_serial(&paths);
+        assert_eq!(serial.len(), 100);
+        assert!(serial.windows(2).all(|w| w[0].sid() <= w[1].sid()));
+        for threads in [1, 3, 8, 200] {
+            assert_eq!(load_sessions_parallel(&paths, threads), serial);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn public_session() -> Result<()> {
+        let fx = Fixture::new("public_session");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let sess = format!("{}/sessions/iqn.1988-12.com.oracle:a", tgt);
+        fx.session(tgt, "iqn.1988-12.com.oracle:a", "10000")
+            .file(format!("{}/192.168.2.30/cid", sess), "0\n")
+            .file(format!("{}/192.168.2.30/ip", sess), "192.168.2.30\n")
+            .file(format!("{}/192.168.2.30/state", sess), "established\n")
+            .file(format!("{}/192.168.2.30/target_ip", sess), "192.168.2.1\n");
+
+        let mut session = Session::default();
+        session.load(fx.path(&sess))?;
+        assert_eq!(session.thread_pid(), "1234");
+
+        let full = serde_json::to_value(&session)?;
+        assert!(full.get("thread_pid").is_some());
+
+        let public = serde_json::to_value(session.public())?;
+        assert!(public.get("thread_pid").is_none());
+        assert!(public.get("root").is_none());
+        assert_eq!(public["sid"], "10000");
+        assert_eq!(public["initiator_name"], "iqn.1988-12.com.oracle:a");
+        assert_eq!(public["ips"][0]["state"], "established");
+
+        Ok(())
+    }
+}
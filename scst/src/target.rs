@@ -1,19 +1,48 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::device_group::DGRP_TARGET_GROUP;
 use crate::{
-    IOStat, Layer, Options, ScstError, Session, cmd_with_options, echo, read_dir, read_fl,
-    read_link, read_stat,
+    BatchResult, Device, IOStat, Layer, Options, ScstError, Session, TargetGroup, cmd_with_options,
+    echo, fail_context, is_static_attr, load_created, load_sessions, parse_bool, parse_ip,
+    parse_mgmt_help, read_bool, read_dir, read_extra, read_fl, read_link, read_mgmt_help,
+    read_stat,
 };
 
 static TARGET_GROUP: &str = "ini_groups";
 static TARGET_LUN: &str = "luns";
 static TARGET_INITIATOR: &str = "initiators";
 static TARGET_SESSION: &str = "sessions";
+static SCST_DEVICE: &str = "devices";
+static SCST_DEVICE_GROUP: &str = "device_groups";
+// the target attributes captured by typed fields
+static TARGET_ATTRS: &[&str] = &[
+    "tid",
+    "rel_tgt_id",
+    "enabled",
+    "last_change",
+    ISCSI_MAX_RECV_DATA_SEGMENT_LENGTH,
+    ISCSI_MAX_BURST_LENGTH,
+    ISCSI_FIRST_BURST_LENGTH,
+    ISCSI_MAX_OUTSTANDING_R2T,
+];
+static DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// the iSCSI negotiation parameters of target
+static ISCSI_MAX_RECV_DATA_SEGMENT_LENGTH: &str = "MaxRecvDataSegmentLength";
+static ISCSI_MAX_BURST_LENGTH: &str = "MaxBurstLength";
+static ISCSI_FIRST_BURST_LENGTH: &str = "FirstBurstLength";
+static ISCSI_MAX_OUTSTANDING_R2T: &str = "MaxOutstandingR2T";
+// the limits of RFC 3720
+const ISCSI_MIN_LENGTH: u32 = 512;
+const ISCSI_MAX_LENGTH: u32 = (1 << 24) - 1;
+const ISCSI_MAX_R2T: u32 = 65535;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Driver {
@@ -23,11 +52,30 @@ pub struct Driver {
     enabled: i8,
     open_state: String,
     version: String,
+    // holds the CHAP secrets of `IncomingUser` and `OutgoingUser`, so it isn't serialized
+    #[serde(skip)]
+    attributes: BTreeMap<String, String>,
 
     targets: BTreeMap<String, Target>,
+    #[serde(skip)]
+    warnings: Vec<String>,
 }
 
 impl Driver {
+    /// an empty driver at `root` which isn't loaded.
+    pub(crate) fn unloaded<P: AsRef<Path>>(root: P) -> Self {
+        let root_ref = root.as_ref();
+        Driver {
+            root: root_ref.to_string_lossy().to_string(),
+            name: root_ref
+                .file_name()
+                .unwrap_or(OsStr::new(""))
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -61,6 +109,28 @@ impl Driver {
         Ok(())
     }
 
+    /// every scalar attribute at the driver root as read from sysfs, like `iSNSServer`,
+    /// `allowed_portal` or `IncomingUser`. A multi-valued attribute is numbered from the second
+    /// value, like `IncomingUser1`.
+    pub fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+
+    /// the value of a scalar attribute of the driver, None if the driver doesn't expose it.
+    pub fn attribute(&self, k: &str) -> Option<&str> {
+        self.attributes.get(k).map(|v| v.as_str())
+    }
+
+    /// the non-fatal problems found by the last load, like a missing `enabled` attribute.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// the raw usage text of the driver `mgmt`, like the attributes of `add_target`.
+    pub fn mgmt_help(&self) -> Result<String> {
+        Layer::mgmt_help(self)
+    }
+
     pub fn open_state(&self) -> &str {
         &self.open_state
     }
@@ -73,6 +143,22 @@ impl Driver {
         self.targets.values().collect()
     }
 
+    /// the number of sessions of all targets. Counting stops at the first target failing to
+    /// read, `ScstError::SessionCountFail` returns with the sessions counted before it.
+    pub fn total_session_count(&self) -> Result<usize> {
+        let mut counted = 0;
+        for target in self.targets.values() {
+            counted += target
+                .session_count()
+                .context(ScstError::SessionCountFail {
+                    target: target.name().to_string(),
+                    counted,
+                })?;
+        }
+
+        Ok(counted)
+    }
+
     pub fn get_target<S: AsRef<str>>(&self, name: S) -> Result<&Target> {
         self.targets
             .get(name.as_ref())
@@ -96,18 +182,48 @@ impl Driver {
     /// scst.iscsi_mut().add_target("iqn.2018-11.com.vine:test", &options)?;
     /// ```
     pub fn add_target<S: AsRef<str>>(&mut self, name: S, options: &Options) -> Result<&mut Target> {
-        let name_ref = name.as_ref();
+        self.add_target_with(name.as_ref(), options, &[])
+    }
+
+    /// like `add_target()`, but pins the `tid` of the target, like for a stable FC port mapping.
+    /// `ScstError::DriverNotVirt` returns if the `mgmt` of driver doesn't take the tid.
+    pub fn add_target_with_tid<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        tid: u64,
+        options: &Options,
+    ) -> Result<&mut Target> {
+        let help = parse_mgmt_help(&self.mgmt_help()?);
+        if !help.attributes("target").iter().any(|attr| attr == "tid") {
+            anyhow::bail!(ScstError::DriverNotVirt)
+        }
+
+        let mut options = options.clone();
+        options.insert("tid", tid.to_string().as_str());
+        self.add_target_with(name.as_ref(), &options, &["tid"])
+    }
+
+    fn add_target_with(
+        &mut self,
+        name_ref: &str,
+        options: &Options,
+        extra_params: &[&str],
+    ) -> Result<&mut Target> {
         if self.targets.contains_key(name_ref) {
             anyhow::bail!(ScstError::TargetExists(name_ref.to_string()))
         }
 
         let root = self.root();
         let mut cmd = format!("add_target {}", name_ref);
-        let params = vec![
+        let mut params = vec![
             "IncomingUser".to_string(),
             "OutgoingUser".to_string(),
             "allowed_portal".to_string(),
         ];
+        params.extend(extra_params.iter().map(|p| p.to_string()));
+        if let Some(portal) = options.get("allowed_portal") {
+            check_portal(portal)?;
+        }
         cmd = options
             .check_pack(&params)?
             .and_then(|s| {
@@ -122,7 +238,7 @@ impl Driver {
         self.mgmt(root.to_path_buf(), cmd.into())?;
 
         let mut target = Target::default();
-        target.load(self.root().join(name_ref))?;
+        load_created(&mut target, self.root().join(name_ref))?;
         self.targets.insert(target.name().to_string(), target);
 
         self.get_target_mut(name_ref)
@@ -180,6 +296,9 @@ impl Driver {
         if !params.contains(&attr.as_ref().to_string()) {
             anyhow::bail!(ScstError::TargetBadAttrs)
         }
+        if attr.as_ref() == "allowed_portal" {
+            check_portal(value.as_ref())?;
+        }
 
         self.mgmt(root.to_path_buf(), cmd.into())?;
 
@@ -217,6 +336,9 @@ impl Driver {
         if !params.contains(&attr.as_ref().to_string()) {
             anyhow::bail!(ScstError::TargetBadAttrs)
         }
+        if attr.as_ref() == "allowed_portal" {
+            check_portal(value.as_ref())?;
+        }
 
         self.mgmt(root.to_path_buf(), cmd.into())?;
 
@@ -238,6 +360,16 @@ impl Driver {
 
         self.mgmt(root.to_path_buf(), cmd.into())?;
 
+        // SCST numbers the values after the first one by the lowest free index
+        let key = (0..)
+            .map(|i| match i {
+                0 => attr.as_ref().to_string(),
+                i => format!("{}{}", attr.as_ref(), i),
+            })
+            .find(|key| !self.attributes.contains_key(key))
+            .unwrap_or_default();
+        self.attributes.insert(key, value.as_ref().to_string());
+
         Ok(())
     }
 
@@ -252,6 +384,14 @@ impl Driver {
 
         self.mgmt(root.to_path_buf(), cmd.into())?;
 
+        let (attr, value) = (attr.as_ref(), value.as_ref());
+        self.attributes.retain(|key, v| {
+            let numbered = key
+                .strip_prefix(attr)
+                .is_some_and(|i| i.chars().all(|c| c.is_ascii_digit()));
+            !(numbered && v == value)
+        });
+
         Ok(())
     }
 }
@@ -272,16 +412,27 @@ impl Layer for Driver {
             .unwrap_or(OsStr::new(""))
             .to_string_lossy()
             .to_string();
-        self.enabled = read_fl(root_ref.join("enabled"))?.parse::<i8>()?;
-        self.open_state = read_fl(root_ref.join("open_state"))?;
+        self.warnings.clear();
+        // some drivers like copy_manager don't expose the `enabled` attribute
+        self.enabled = match read_fl(root_ref.join("enabled")) {
+            Ok(enabled) => parse_bool(root_ref.join("enabled"), enabled)? as i8,
+            Err(e) => {
+                self.warnings.push(format!(
+                    "failed to read enabled of driver {}: {}",
+                    self.name, e
+                ));
+                0
+            }
+        };
+        // `open_state` is reported by iscsi only
+        self.open_state = read_fl(root_ref.join("open_state")).unwrap_or_default();
         self.version = read_fl(root_ref.join("version"))?;
+        self.attributes = read_extra(root_ref, &[]);
 
         // traverse target directory
         self.targets = read_dir(root_ref)?
             .filter_map(|res| res.ok())
-            .filter(|entry| {
-                entry.path().is_dir() && entry.file_name().to_string_lossy().starts_with("iqn")
-            })
+            .filter(|entry| entry.path().is_dir())
             .filter_map(|entry| {
                 let mut target = Target::default();
                 target.set_name(entry.file_name().to_string_lossy());
@@ -294,6 +445,24 @@ impl Layer for Driver {
     }
 }
 
+/// an enable or disable of the target performed by this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnabledTransition {
+    enabled: bool,
+    at: SystemTime,
+}
+
+impl EnabledTransition {
+    /// the state the target transitioned to.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn at(&self) -> SystemTime {
+        self.at
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Target {
     #[serde(skip)]
@@ -302,9 +471,20 @@ pub struct Target {
     rel_tgt_id: u64,
     name: String,
     enabled: i8,
+    #[serde(default)]
+    last_change: Option<String>,
+    // every scalar attribute, the iSCSI parameters and CHAP secrets included, so it isn't
+    // serialized
+    #[serde(skip)]
+    attributes: BTreeMap<String, String>,
 
     luns: BTreeMap<String, Lun>,
     ini_groups: BTreeMap<String, IniGroup>,
+
+    #[serde(skip)]
+    cached_sessions: Vec<Session>,
+    #[serde(skip)]
+    transitions: Vec<EnabledTransition>,
 }
 
 impl Target {
@@ -316,6 +496,18 @@ impl Target {
         self.rel_tgt_id
     }
 
+    /// sets the relative target id, SCST refuses to change it while the target is enabled.
+    pub fn set_rel_tgt_id(&mut self, id: u64) -> Result<()> {
+        echo(
+            self.root().join("rel_tgt_id"),
+            id.to_string().as_str().into(),
+        )
+        .map_err(|e| fail_context(e, ScstError::TargetSetAttrFail("rel_tgt_id".to_string())))?;
+
+        self.rel_tgt_id = id;
+        Ok(())
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -324,6 +516,103 @@ impl Target {
         self.name = name.as_ref().to_string()
     }
 
+    /// the readable attributes without typed support, like `addr_method` or the driver
+    /// specific ones.
+    pub fn extra(&self) -> BTreeMap<String, String> {
+        self.attributes
+            .iter()
+            .filter(|(key, _)| !TARGET_ATTRS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// every scalar attribute of the target as read from sysfs, typed ones included, like
+    /// `QueuedCommands`, `DataDigest` or `IncomingUser`.
+    pub fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+
+    /// the value of a scalar attribute of the target, None if the target doesn't expose it.
+    pub fn attribute<S: AsRef<str>>(&self, key: S) -> Option<&str> {
+        self.attributes.get(key.as_ref()).map(|v| v.as_str())
+    }
+
+    /// the `MaxRecvDataSegmentLength` offered in iSCSI negotiation, None if the driver
+    /// doesn't expose it.
+    pub fn max_recv_data_segment_length(&self) -> Option<u32> {
+        self.iscsi_param(ISCSI_MAX_RECV_DATA_SEGMENT_LENGTH)
+    }
+
+    /// sets `MaxRecvDataSegmentLength`, a power of two between 512 and 2^24-1.
+    pub fn set_max_recv_data_segment_length(&mut self, value: u32) -> Result<()> {
+        self.set_iscsi_param(ISCSI_MAX_RECV_DATA_SEGMENT_LENGTH, value)
+    }
+
+    /// the `MaxBurstLength` offered in iSCSI negotiation.
+    pub fn max_burst_length(&self) -> Option<u32> {
+        self.iscsi_param(ISCSI_MAX_BURST_LENGTH)
+    }
+
+    /// sets `MaxBurstLength`, a power of two between 512 and 2^24-1, not less than
+    /// `FirstBurstLength`.
+    pub fn set_max_burst_length(&mut self, value: u32) -> Result<()> {
+        self.set_iscsi_param(ISCSI_MAX_BURST_LENGTH, value)
+    }
+
+    /// the `FirstBurstLength` offered in iSCSI negotiation.
+    pub fn first_burst_length(&self) -> Option<u32> {
+        self.iscsi_param(ISCSI_FIRST_BURST_LENGTH)
+    }
+
+    /// sets `FirstBurstLength`, a power of two between 512 and 2^24-1, not greater than
+    /// `MaxBurstLength`.
+    pub fn set_first_burst_length(&mut self, value: u32) -> Result<()> {
+        self.set_iscsi_param(ISCSI_FIRST_BURST_LENGTH, value)
+    }
+
+    /// the `MaxOutstandingR2T` offered in iSCSI negotiation.
+    pub fn max_outstanding_r2t(&self) -> Option<u32> {
+        self.iscsi_param(ISCSI_MAX_OUTSTANDING_R2T)
+    }
+
+    /// sets `MaxOutstandingR2T`, between 1 and 65535.
+    pub fn set_max_outstanding_r2t(&mut self, value: u32) -> Result<()> {
+        self.set_iscsi_param(ISCSI_MAX_OUTSTANDING_R2T, value)
+    }
+
+    fn iscsi_param(&self, name: &str) -> Option<u32> {
+        self.attributes.get(name)?.parse::<u32>().ok()
+    }
+
+    fn set_iscsi_param(&mut self, name: &str, value: u32) -> Result<()> {
+        let valid = if name == ISCSI_MAX_OUTSTANDING_R2T {
+            (1..=ISCSI_MAX_R2T).contains(&value)
+        } else {
+            value.is_power_of_two() && (ISCSI_MIN_LENGTH..=ISCSI_MAX_LENGTH).contains(&value)
+        };
+        let bursts_ok = match name {
+            n if n == ISCSI_FIRST_BURST_LENGTH => self
+                .max_burst_length()
+                .map_or(true, |max_burst| value <= max_burst),
+            n if n == ISCSI_MAX_BURST_LENGTH => self
+                .first_burst_length()
+                .map_or(true, |first_burst| value >= first_burst),
+            _ => true,
+        };
+        if !valid || !bursts_ok {
+            anyhow::bail!(ScstError::TargetBadParam {
+                name: name.to_string(),
+                value,
+            })
+        }
+
+        echo(self.root().join(name), value.to_string().as_str().into())
+            .map_err(|e| fail_context(e, ScstError::TargetSetAttrFail(name.to_string())))?;
+
+        self.attributes.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
     /// get scst target state
     pub fn enabled(&self) -> bool {
         self.enabled == 1
@@ -333,6 +622,17 @@ impl Target {
         self.enabled
     }
 
+    /// the `last_change` attribute reported by some drivers, None if it's unavailable.
+    pub fn last_change(&self) -> Option<&str> {
+        self.last_change.as_deref()
+    }
+
+    /// the enables and disables performed through this `Target`, oldest first. They are
+    /// tracked in memory only, so the changes made by others aren't included.
+    pub fn transitions(&self) -> &[EnabledTransition] {
+        &self.transitions
+    }
+
     /// enable scst target
     pub fn enable(&mut self) -> Result<()> {
         let root = self.root().join("enabled");
@@ -340,6 +640,7 @@ impl Target {
         echo(root, cmd.into())?;
 
         self.enabled = 1;
+        self.record_transition(true);
         Ok(())
     }
 
@@ -350,14 +651,28 @@ impl Target {
         echo(root, cmd.into())?;
 
         self.enabled = 0;
+        self.record_transition(false);
 
         Ok(())
     }
 
+    fn record_transition(&mut self, enabled: bool) {
+        self.transitions.push(EnabledTransition {
+            enabled,
+            at: SystemTime::now(),
+        });
+    }
+
     pub fn luns(&self) -> Vec<&Lun> {
         self.luns.values().collect()
     }
 
+    /// the raw usage text of the LUNs `mgmt`, like the options of `add`. The target directory
+    /// has no `mgmt` of its own.
+    pub fn mgmt_help(&self) -> Result<String> {
+        read_mgmt_help(self.root().join(TARGET_LUN))
+    }
+
     pub fn get_lun<S: AsRef<str>>(&self, lun_id: S) -> Result<&Lun> {
         self.luns
             .get(lun_id.as_ref())
@@ -370,7 +685,17 @@ impl Target {
             .context(ScstError::TargetNoLun(lun_id.as_ref().to_string()))
     }
 
-    /// create a lun for target.
+    /// the LUN by its numeric id, like 0 for `lun0`.
+    pub fn get_lun_by_id(&self, id: u64) -> Result<&Lun> {
+        self.get_lun(format!("lun{}", id))
+    }
+
+    pub fn get_lun_by_id_mut(&mut self, id: u64) -> Result<&mut Lun> {
+        self.get_lun_mut(format!("lun{}", id))
+    }
+
+    /// create a lun for target. A read-only device requires `read_only` given in options,
+    /// otherwise `ScstError::LunReadOnlyDevice` returns.
     ///
     /// ```no_run
     /// use scst::{Scst, Options}
@@ -390,22 +715,124 @@ impl Target {
         if self.luns.contains_key(&format!("lun{}", &id_ref)) {
             anyhow::bail!(ScstError::TargetLunExists(id_ref.clone()))
         }
+        check_lun_read_only(self.root().ancestors().nth(3), device.as_ref(), options)?;
 
         let mut cmd = format!("add {} {}", device.as_ref(), &id_ref);
         let params = vec!["read_only".to_string()];
         cmd = cmd_with_options(&cmd, &params, &options)?;
 
         let root = self.root().join(TARGET_LUN);
-        self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::TargetAddLunFail(id_ref.clone()))?;
+        self.mgmt(root.clone(), cmd.into()).map_err(|e| {
+            let cause = add_lun_cause(
+                &e,
+                self.root().ancestors().nth(3),
+                &root,
+                device.as_ref(),
+                &id_ref,
+            );
+            e.context(cause.unwrap_or(ScstError::TargetAddLunFail(id_ref.clone())))
+        })?;
 
         let mut lun = Lun::default();
-        lun.load(self.root().join(TARGET_LUN).join(&id_ref))?;
+        load_created(&mut lun, self.root().join(TARGET_LUN).join(&id_ref))?;
         self.luns.insert(lun.name().to_string(), lun);
 
         Ok(())
     }
 
+    /// returns the LUN `lun_id` if it already maps `device`, or adds it otherwise.
+    /// `ScstError::LunDeviceExists` returns if the LUN maps another device.
+    pub fn ensure_lun<S: AsRef<str>>(
+        &mut self,
+        device: S,
+        lun_id: u64,
+        options: &Options,
+    ) -> Result<&Lun> {
+        let name = format!("lun{}", lun_id);
+        match self.luns.get(&name) {
+            Some(lun) if lun.device() != device.as_ref() => {
+                anyhow::bail!(ScstError::LunDeviceExists(lun.device().to_string()))
+            }
+            Some(_) => {}
+            None => self.add_lun(device, lun_id, options)?,
+        }
+
+        self.get_lun_by_id(lun_id)
+    }
+
+    /// the smallest LUN id unused by the target.
+    pub fn next_free_lun_id(&self) -> u64 {
+        (0..)
+            .find(|id| !self.luns.contains_key(&format!("lun{}", id)))
+            .unwrap_or_default()
+    }
+
+    /// adds the LUN of `device` at the next free LUN id, which returns.
+    pub fn add_lun_auto<S: AsRef<str>>(&mut self, device: S, options: &Options) -> Result<u64> {
+        let lun_id = self.next_free_lun_id();
+        self.add_lun(device, lun_id, options)?;
+
+        Ok(lun_id)
+    }
+
+    /// adds the LUN at the target level and into each of the named initiator groups, like to
+    /// expose a device broadly and to an ACL'd group too. The added LUNs are deleted again if
+    /// any of them fails.
+    pub fn add_lun_to_groups<S: AsRef<str>>(
+        &mut self,
+        device: S,
+        lun_id: u64,
+        groups: &[&str],
+        options: &Options,
+    ) -> Result<()> {
+        let device = device.as_ref();
+        self.add_lun(device, lun_id, options)?;
+
+        let mut added = Vec::new();
+        for group in groups {
+            let res = self
+                .get_ini_group_mut(group)
+                .and_then(|g| g.add_lun(device, lun_id, options));
+            if let Err(e) = res {
+                for group in added {
+                    if let Ok(g) = self.get_ini_group_mut(group) {
+                        g.del_lun_force(lun_id).ok();
+                    }
+                }
+                self.del_lun_force(lun_id).ok();
+                return Err(e);
+            }
+            added.push(*group);
+        }
+
+        Ok(())
+    }
+
+    /// adds the LUNs of `(device, lun id, options)` one by one, the failed ones don't stop the
+    /// others. The LUN ids are returned in the result.
+    pub fn add_luns(&mut self, luns: &[(&str, u64, Options)]) -> BatchResult<u64> {
+        let mut batch = BatchResult::new();
+        for (device, id, options) in luns {
+            let res = self.add_lun(*device, *id, options);
+            batch.push(*id, res);
+        }
+
+        batch
+    }
+
+    /// like `add_lun()`, but waits the device to be active firstly, so a just created device
+    /// isn't exposed before it's ready.
+    pub fn add_lun_wait(
+        &mut self,
+        device: &Device,
+        lun_id: u64,
+        options: &Options,
+        timeout: Duration,
+    ) -> Result<()> {
+        device.wait_active(timeout)?;
+        self.add_lun(device.name(), lun_id, options)
+    }
+
     pub fn set_lun<S: AsRef<str>>(
         &mut self,
         device: S,
@@ -424,16 +851,18 @@ impl Target {
 
         let root = self.root().join(TARGET_LUN);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::LunSetAttrFail(id_ref.clone()))?;
+            .map_err(|e| fail_context(e, ScstError::LunSetAttrFail(id_ref.clone())))?;
 
         let mut lun = Lun::default();
-        lun.load(self.root().join(TARGET_LUN).join(&id_ref))?;
+        load_created(&mut lun, self.root().join(TARGET_LUN).join(&id_ref))?;
         self.luns.insert(lun.name().to_string(), lun);
 
         Ok(())
     }
 
-    /// delete a lun for target.
+    /// delete a lun for target. Deleting the only LUN exporting a device while the target has
+    /// active sessions strands the initiators, `ScstError::LastPathToDevice` returns for it,
+    /// use `del_lun_force()` to delete anyway.
     ///
     /// ```no_run
     /// use scst::{Scst, Options}
@@ -444,6 +873,18 @@ impl Target {
     /// target.del_lun(0)?;
     /// ```
     pub fn del_lun(&mut self, lun_id: u64) -> Result<()> {
+        let id_ref = lun_id.to_string();
+        let lun = self
+            .luns
+            .get(&format!("lun{}", &id_ref))
+            .context(ScstError::TargetNoLun(id_ref.clone()))?;
+        check_last_path(self.root().ancestors().nth(3), self.root(), lun.device())?;
+
+        self.del_lun_force(lun_id)
+    }
+
+    /// like `del_lun()`, but deletes the last LUN exporting a device in use.
+    pub fn del_lun_force(&mut self, lun_id: u64) -> Result<()> {
         let id_ref = lun_id.to_string();
         let name = format!("lun{}", &id_ref);
         if !self.luns.contains_key(&name) {
@@ -453,17 +894,161 @@ impl Target {
         let root = self.root().join(TARGET_LUN);
         let cmd = format!("del {}", &id_ref);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::TargetRemLunFail(id_ref.clone()))?;
+            .map_err(|e| fail_context(e, ScstError::TargetRemLunFail(id_ref.clone())))?;
 
         self.luns.remove(&name);
 
         Ok(())
     }
 
+    /// sets every LUN of the target read-only or writable, like for a maintenance, returns the
+    /// number of LUNs changed. The LUNs already in the state are skipped. It goes on after a
+    /// failed LUN, and fails only if every change failed.
+    pub fn set_all_luns_read_only(&mut self, ro: bool) -> Result<usize> {
+        let mut batch = BatchResult::new();
+        for lun in self.luns.values_mut() {
+            if lun.read_only() != ro {
+                let res = lun.set_read_only(ro);
+                batch.push(lun.id(), res);
+            }
+        }
+
+        if batch.succeeded().is_empty() && !batch.all_ok() {
+            let reasons = batch
+                .failed()
+                .iter()
+                .map(|(id, e)| format!("lun {}: {}", id, e))
+                .collect::<Vec<String>>()
+                .join("; ");
+            return Err(anyhow::anyhow!(reasons)
+                .context(ScstError::LunSetAttrFail("read_only".to_string())));
+        }
+
+        Ok(batch.succeeded().len())
+    }
+
+    /// renumbers the LUN `from` to `to` with the same options: its device, `read_only`, which
+    /// is the only option of adding a LUN, and the `alias` set afterwards. The LUN is restored
+    /// at `from` if it fails to be added at `to`, the failure of adding it returns either way.
+    pub fn move_lun(&mut self, from: u64, to: u64) -> Result<()> {
+        let lun = self.get_lun_by_id(from)?;
+        let device = lun.device().to_string();
+        let options = lun.add_options();
+        let alias = lun.alias().map(|alias| alias.to_string());
+        if self.luns.contains_key(&format!("lun{}", to)) {
+            anyhow::bail!(ScstError::TargetLunExists(to.to_string()))
+        }
+
+        // the LUN is added back right away, so it's no last path removed
+        self.del_lun_force(from)?;
+        let id = match self.add_lun(device.as_str(), to, &options) {
+            Ok(_) => to,
+            Err(e) => {
+                let restored =
+                    self.add_lun(device.as_str(), from, &options)
+                        .and_then(|_| match &alias {
+                            Some(alias) => self.get_lun_by_id_mut(from)?.set_alias(alias),
+                            None => Ok(()),
+                        });
+                return match restored {
+                    Ok(_) => Err(e),
+                    Err(re) => Err(e.context(format!("failed to restore lun{}: {:#}", from, re))),
+                };
+            }
+        };
+        if let Some(alias) = &alias {
+            self.get_lun_by_id_mut(id)?.set_alias(alias)?;
+        }
+
+        Ok(())
+    }
+
+    /// replaces the device of the LUN `lun_id` by `new_device` with no command in flight. The
+    /// LUN is replaced by itself read-only, the commands of all sessions of the target, not
+    /// only the ones of the LUN, are waited to drain within `timeout`, then the device is
+    /// replaced with the original options of the LUN, which makes it writable again. The
+    /// initiators see the LUN read-only for the window, a LUN read-only before stays so.
+    /// `ScstError::LunDrainTimeout` returns if the commands don't drain, with the LUN restored.
+    pub fn migrate_lun<S: AsRef<str>>(
+        &mut self,
+        lun_id: u64,
+        new_device: S,
+        timeout: Duration,
+    ) -> Result<()> {
+        let lun = self.get_lun_by_id(lun_id)?;
+        let (device, options) = (lun.device().to_string(), lun.add_options());
+        // the read_only attribute of a LUN can't be written, only `add` and `replace` set it
+        let writable = !lun.read_only();
+        if writable {
+            let mut read_only = options.clone();
+            read_only.insert("read_only", "1");
+            self.set_lun(device.as_str(), lun_id, &read_only)?;
+        }
+
+        let res = self
+            .wait_commands_drained(lun_id, timeout)
+            .and_then(|_| self.set_lun(new_device, lun_id, &options));
+        match res {
+            Err(e) if writable => match self.set_lun(device.as_str(), lun_id, &options) {
+                Ok(_) => Err(e),
+                Err(re) => Err(e.context(format!("failed to restore lun{}: {:#}", lun_id, re))),
+            },
+            res => res,
+        }
+    }
+
+    // the active commands are only reported by session, so the whole target is waited for
+    fn wait_commands_drained(&self, lun_id: u64, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let active = self
+                .sessions()?
+                .iter()
+                .map(|session| session.active_commands().unwrap_or(0))
+                .sum::<u64>();
+            if active == 0 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(ScstError::LunDrainTimeout(lun_id.to_string()))
+            }
+            thread::sleep(
+                DRAIN_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+            );
+        }
+    }
+
     pub fn ini_groups(&self) -> Vec<&IniGroup> {
         self.ini_groups.values().collect()
     }
 
+    /// the target exposes LUNs to any initiator: no CHAP `IncomingUser` is configured on the
+    /// target nor on its driver, whose users apply to all its targets, and the target level
+    /// LUNs, which serve the initiators out of all groups, aren't empty.
+    pub fn is_open_access(&self) -> Result<bool> {
+        let has_user = |attrs: &BTreeMap<String, String>| {
+            attrs
+                .iter()
+                .any(|(key, user)| key.starts_with("IncomingUser") && !user.is_empty())
+        };
+        // the target is loaded alone at times, so the driver attributes are read here
+        let driver = self
+            .root()
+            .parent()
+            .map(|root| read_extra(root, &[]))
+            .unwrap_or_default();
+
+        Ok(!has_user(&self.attributes) && !has_user(&driver) && !self.luns.is_empty())
+    }
+
+    /// the initiator groups which don't contain the initiator `iqn` yet.
+    pub fn groups_without_initiator(&self, iqn: &str) -> Vec<&IniGroup> {
+        self.ini_groups
+            .values()
+            .filter(|group| !group.contains_initiator(iqn))
+            .collect()
+    }
+
     pub fn get_ini_group<S: AsRef<str>>(&self, name: S) -> Result<&IniGroup> {
         self.ini_groups
             .get(name.as_ref())
@@ -497,7 +1082,7 @@ impl Target {
         self.mgmt(root, cmd.into())?;
 
         let mut group = IniGroup::default();
-        group.load(self.root().join(TARGET_GROUP).join(name_ref))?;
+        load_created(&mut group, self.root().join(TARGET_GROUP).join(name_ref))?;
         self.ini_groups.insert(group.name().to_string(), group);
 
         self.get_ini_group_mut(name)
@@ -528,21 +1113,267 @@ impl Target {
         Ok(())
     }
 
+    /// create a initiator group for target, and populates it with the LUNs and initiators.
+    /// The group is deleted if any of them fails to add, so no half-populated group is left.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::{Options, Scst};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let target = scst.iscsi_mut().get_target_mut("iqn.2018-11.com.vine:test")?;
+    ///     target.create_ini_group_with(
+    ///         "test",
+    ///         &[("disk1", 0, Options::new())],
+    ///         &["iqn.1988-12.com.oracle:d4ebaa45254"],
+    ///     )?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn create_ini_group_with<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        luns: &[(&str, u64, Options)],
+        initiators: &[&str],
+    ) -> Result<&mut IniGroup> {
+        let name_ref = name.as_ref();
+        let group = self.create_ini_group(name_ref)?;
+
+        let populated = luns
+            .iter()
+            .try_for_each(|(device, id, options)| group.add_lun(*device, *id, options))
+            .and_then(|_| {
+                initiators
+                    .iter()
+                    .try_for_each(|ini| group.add_initiator(*ini))
+            });
+        if let Err(e) = populated {
+            self.del_ini_group(name_ref).ok();
+            return Err(e);
+        }
+
+        self.get_ini_group_mut(name_ref)
+    }
+
+    /// the ALUA target group id of the target, returns None if the target isn't added to
+    /// any target group.
+    pub fn tpg_id(&self) -> Result<Option<u64>> {
+        let dgrp_root = match self.root().ancestors().nth(3) {
+            Some(root) => root.join(SCST_DEVICE_GROUP),
+            None => return Ok(None),
+        };
+        if !dgrp_root.exists() {
+            return Ok(None);
+        }
+
+        for dgrp in read_dir(dgrp_root)?.filter_map(|res| res.ok()) {
+            let tgrp_root = dgrp.path().join(DGRP_TARGET_GROUP);
+            if !tgrp_root.is_dir() {
+                continue;
+            }
+            for tgrp in read_dir(tgrp_root)?.filter_map(|res| res.ok()) {
+                if tgrp.path().join(&self.name).is_dir() {
+                    let id = read_fl(tgrp.path().join("group_id"))?.parse::<u64>()?;
+                    return Ok(Some(id));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// add the target to an ALUA target group.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let tpg = scst.get_device_group("dg")?.get_target_group("tg")?.clone();
+    ///     let target = scst.iscsi_mut().get_target_mut("iqn.2018-11.com.vine:test")?;
+    ///     target.set_tpg(&tpg)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_tpg(&mut self, tpg: &TargetGroup) -> Result<()> {
+        let cmd = format!("add {}", self.name);
+        self.mgmt(tpg.root().to_path_buf(), cmd.into())
+            .map_err(|e| fail_context(e, ScstError::TargetGroupAddTgtFail(self.name.clone())))?;
+
+        Ok(())
+    }
+
     pub fn io_stat(&self) -> Result<IOStat> {
         read_stat(self.root())
     }
 
+    /// a multi-line human summary of target, like `target show` of a CLI prints.
+    ///
+    /// ```text
+    /// Target: iqn.2018-11.com.vine:vol
+    ///   Enabled: yes
+    ///   Relative target id: 1
+    ///   LUNs: 1
+    ///     lun0: vol1
+    ///   Groups: 1
+    ///     grp: 2 initiators
+    ///   Sessions: 0
+    /// ```
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!("Target: {}", self.name),
+            format!("  Enabled: {}", if self.enabled() { "yes" } else { "no" }),
+            format!("  Relative target id: {}", self.rel_tgt_id),
+            format!("  LUNs: {}", self.luns.len()),
+        ];
+        for lun in self.luns() {
+            lines.push(format!("    {}: {}", lun.name(), lun.device()));
+        }
+        lines.push(format!("  Groups: {}", self.ini_groups.len()));
+        for group in self.ini_groups() {
+            lines.push(format!(
+                "    {}: {} initiators",
+                group.name(),
+                group.initiators().len()
+            ));
+        }
+        let sessions = match self.session_count() {
+            Ok(count) => count.to_string(),
+            Err(_) => "unknown".to_string(),
+        };
+        lines.push(format!("  Sessions: {}", sessions));
+
+        lines.join("\n")
+    }
+
+    /// the number of sessions of target, without reading the sessions.
+    pub fn session_count(&self) -> Result<usize> {
+        let count = read_dir(self.root().join(TARGET_SESSION))?
+            .filter_map(|res| res.ok())
+            .filter(|entry| entry.path().is_dir())
+            .count();
+
+        Ok(count)
+    }
+
+    /// reads the sessions of target from sysfs, sorted by sid. Every call re-reads the whole
+    /// sessions directory, use `cached_sessions()` in a monitoring loop to avoid re-stating
+    /// everything.
     pub fn sessions(&self) -> Result<Vec<Session>> {
-        let sessions = read_dir(self.root().join(TARGET_SESSION))?
+        let paths = read_dir(self.root().join(TARGET_SESSION))?
             .filter_map(|res| res.ok())
-            .filter_map(|entry| {
-                let mut session = Session::default();
-                session.load(entry.path()).ok();
-                Some(session)
-            })
-            .collect();
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>();
+
+        Ok(load_sessions(&paths))
+    }
+
+    /// forcibly closes the session with the `sid`, the target stays enabled for the other
+    /// initiators. `ScstError::NoSession` returns if no session matches.
+    pub fn close_session<S: AsRef<str>>(&mut self, sid: S) -> Result<()> {
+        let sid = sid.as_ref();
+        let session = self
+            .sessions()?
+            .into_iter()
+            .find(|session| session.sid() == sid)
+            .context(ScstError::NoSession)?;
+
+        session.force_close()
+    }
+
+    /// forcibly closes every session of the target, like before deleting a busy target. It goes
+    /// on after a failed close, and fails only if every close failed. The number of sessions
+    /// attempted returns.
+    pub fn close_all_sessions(&mut self) -> Result<usize> {
+        let mut batch = BatchResult::new();
+        for session in self.sessions()? {
+            let res = session.force_close();
+            batch.push(session.sid().to_string(), res);
+        }
+
+        if batch.succeeded().is_empty() && !batch.all_ok() {
+            let reasons = batch
+                .failed()
+                .iter()
+                .map(|(sid, e)| format!("session {}: {}", sid, e))
+                .collect::<Vec<String>>()
+                .join("; ");
+            return Err(anyhow::anyhow!(reasons).context(ScstError::SessionCloseFail));
+        }
+
+        Ok(batch.succeeded().len() + batch.failed().len())
+    }
+
+    /// the initiators of the groups with no live session, sorted, like the ACL entries of
+    /// decommissioned hosts to prune.
+    pub fn idle_initiators(&self) -> Result<Vec<String>> {
+        let connected = self
+            .sessions()?
+            .iter()
+            .map(|session| normalize_initiator(session.initiator_name()))
+            .collect::<Vec<String>>();
+
+        let mut idle = self
+            .ini_groups
+            .values()
+            .flat_map(|group| group.initiators())
+            .filter(|ini| !connected.contains(&normalize_initiator(ini)))
+            .cloned()
+            .collect::<Vec<String>>();
+        idle.sort();
+        idle.dedup();
+
+        Ok(idle)
+    }
+
+    /// closes the sessions connected longer than `older_than`, returns the number of sessions
+    /// closed. The sessions of unknown age are skipped, see `Session::connected_duration()`.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let target = scst.iscsi_mut().get_target_mut("iqn.2018-11.com.vine:test")?;
+    ///     let closed = target.close_stale_sessions(Duration::from_secs(24 * 3600))?;
+    ///     println!("{} sessions closed", closed);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn close_stale_sessions(&mut self, older_than: Duration) -> Result<usize> {
+        let mut closed = 0;
+        for session in self.sessions()? {
+            match session.connected_duration() {
+                Some(age) if age > older_than => {
+                    session.force_close()?;
+                    closed += 1;
+                }
+                _ => continue,
+            }
+        }
+
+        Ok(closed)
+    }
+
+    /// the sessions read by the last `refresh_sessions()`, it's empty before the first
+    /// refresh. They may be stale, call `refresh_sessions()` to control the freshness.
+    pub fn cached_sessions(&self) -> &[Session] {
+        &self.cached_sessions
+    }
 
-        Ok(sessions)
+    /// re-reads the sessions into the cache of `cached_sessions()`.
+    pub fn refresh_sessions(&mut self) -> Result<()> {
+        self.cached_sessions = self.sessions()?;
+        Ok(())
     }
 }
 
@@ -563,9 +1394,12 @@ impl Layer for Target {
             .unwrap_or("0".to_string())
             .parse::<u64>()?;
         self.rel_tgt_id = read_fl(root_ref.join("rel_tgt_id"))?.parse::<u64>()?;
-        self.enabled = read_fl(root_ref.join("enabled"))
-            .unwrap_or("1".to_string())
-            .parse::<i8>()?;
+        self.enabled = match read_fl(root_ref.join("enabled")) {
+            Ok(enabled) => parse_bool(root_ref.join("enabled"), enabled)? as i8,
+            Err(_) => 1,
+        };
+        self.last_change = read_fl(root_ref.join("last_change")).ok();
+        self.attributes = read_extra(root_ref, &[]);
 
         // traverse target luns
         self.luns = read_dir(root_ref.join(TARGET_LUN))?
@@ -612,6 +1446,12 @@ impl IniGroup {
         self.luns.values().collect()
     }
 
+    /// the raw usage text of the group LUNs `mgmt`, like the options of `add`. The group
+    /// directory has no `mgmt` of its own.
+    pub fn mgmt_help(&self) -> Result<String> {
+        read_mgmt_help(self.root().join(TARGET_LUN))
+    }
+
     pub fn get_lun<S: AsRef<str>>(&self, lun_id: S) -> Result<&Lun> {
         self.luns
             .get(lun_id.as_ref())
@@ -624,7 +1464,17 @@ impl IniGroup {
             .context(ScstError::GroupNoLun(lun_id.as_ref().to_string()))
     }
 
-    /// create a lun for target initiator group.
+    /// the LUN by its numeric id, like 0 for `lun0`.
+    pub fn get_lun_by_id(&self, id: u64) -> Result<&Lun> {
+        self.get_lun(format!("lun{}", id))
+    }
+
+    pub fn get_lun_by_id_mut(&mut self, id: u64) -> Result<&mut Lun> {
+        self.get_lun_mut(format!("lun{}", id))
+    }
+
+    /// create a lun for target initiator group. A read-only device requires `read_only` given
+    /// in options, otherwise `ScstError::LunReadOnlyDevice` returns.
     ///
     /// ```no_run
     /// use scst::{Scst, Options}
@@ -646,50 +1496,93 @@ impl IniGroup {
         if self.luns.contains_key(&name) {
             anyhow::bail!(ScstError::GroupLunExists(id_ref.clone()))
         }
+        check_lun_read_only(self.root().ancestors().nth(5), device.as_ref(), options)?;
 
         let mut cmd = format!("add {} {}", device.as_ref(), &id_ref);
         let params = vec!["read_only".to_string()];
         cmd = cmd_with_options(&cmd, &params, &options)?;
 
         let root = self.root().join(TARGET_LUN);
-        self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupAddLunFail(id_ref.clone()))?;
+        self.mgmt(root.clone(), cmd.into()).map_err(|e| {
+            let cause = add_lun_cause(
+                &e,
+                self.root().ancestors().nth(5),
+                &root,
+                device.as_ref(),
+                &id_ref,
+            );
+            e.context(cause.unwrap_or(ScstError::GroupAddLunFail(id_ref.clone())))
+        })?;
 
         let mut lun = Lun::default();
-        lun.load(self.root().join(TARGET_LUN).join(&id_ref))?;
+        load_created(&mut lun, self.root().join(TARGET_LUN).join(&id_ref))?;
         self.luns.insert(lun.name().to_string(), lun);
 
         Ok(())
     }
 
-    pub fn set_lun<S: AsRef<str>>(
+    /// returns the LUN `lun_id` if it already maps `device`, or adds it otherwise.
+    /// `ScstError::LunDeviceExists` returns if the LUN maps another device.
+    pub fn ensure_lun<S: AsRef<str>>(
         &mut self,
         device: S,
         lun_id: u64,
         options: &Options,
-    ) -> Result<()> {
-        let id_ref = lun_id.to_string();
-        let name = format!("lun{}", &id_ref);
-        if !self.luns.contains_key(&name) {
-            anyhow::bail!(ScstError::GroupNoLun(id_ref.clone()))
+    ) -> Result<&Lun> {
+        let name = format!("lun{}", lun_id);
+        match self.luns.get(&name) {
+            Some(lun) if lun.device() != device.as_ref() => {
+                anyhow::bail!(ScstError::LunDeviceExists(lun.device().to_string()))
+            }
+            Some(_) => {}
+            None => self.add_lun(device, lun_id, options)?,
         }
 
-        let mut cmd = format!("replace {} {}", device.as_ref(), &id_ref);
-        let params = vec!["read_only".to_string()];
-        cmd = cmd_with_options(&cmd, &params, &options)?;
+        self.get_lun_by_id(lun_id)
+    }
+
+    /// like `add_lun()`, but waits the device to be active firstly, so a just created device
+    /// isn't exposed before it's ready.
+    pub fn add_lun_wait(
+        &mut self,
+        device: &Device,
+        lun_id: u64,
+        options: &Options,
+        timeout: Duration,
+    ) -> Result<()> {
+        device.wait_active(timeout)?;
+        self.add_lun(device.name(), lun_id, options)
+    }
+
+    pub fn set_lun<S: AsRef<str>>(
+        &mut self,
+        device: S,
+        lun_id: u64,
+        options: &Options,
+    ) -> Result<()> {
+        let id_ref = lun_id.to_string();
+        let name = format!("lun{}", &id_ref);
+        if !self.luns.contains_key(&name) {
+            anyhow::bail!(ScstError::GroupNoLun(id_ref.clone()))
+        }
+
+        let mut cmd = format!("replace {} {}", device.as_ref(), &id_ref);
+        let params = vec!["read_only".to_string()];
+        cmd = cmd_with_options(&cmd, &params, &options)?;
 
         let root = self.root().join(TARGET_LUN);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::LunSetAttrFail(id_ref.clone()))?;
+            .map_err(|e| fail_context(e, ScstError::LunSetAttrFail(id_ref.clone())))?;
 
         let mut lun = Lun::default();
-        lun.load(self.root().join(TARGET_LUN).join(&id_ref))?;
+        load_created(&mut lun, self.root().join(TARGET_LUN).join(&id_ref))?;
         self.luns.insert(lun.name().to_string(), lun);
 
         Ok(())
     }
 
-    /// delete a lun for target initiator group.
+    /// delete a lun for target initiator group. Like `Target::del_lun()`, the only LUN
+    /// exporting a device in use by active sessions is refused.
     ///
     /// ```no_run
     /// use scst::{Scst, Options}
@@ -701,6 +1594,20 @@ impl IniGroup {
     /// group.del_lun(0)?;
     /// ```
     pub fn del_lun(&mut self, lun_id: u64) -> Result<()> {
+        let id_ref = lun_id.to_string();
+        let lun = self
+            .luns
+            .get(&format!("lun{}", &id_ref))
+            .context(ScstError::GroupNoLun(id_ref.clone()))?;
+        if let Some(target_root) = self.root().ancestors().nth(2) {
+            check_last_path(self.root().ancestors().nth(5), target_root, lun.device())?;
+        }
+
+        self.del_lun_force(lun_id)
+    }
+
+    /// like `del_lun()`, but deletes the last LUN exporting a device in use.
+    pub fn del_lun_force(&mut self, lun_id: u64) -> Result<()> {
         let id_ref = lun_id.to_string();
         let name = format!("lun{}", &id_ref);
         if !self.luns.contains_key(&name) {
@@ -710,7 +1617,7 @@ impl IniGroup {
         let root = self.root().join(TARGET_LUN);
         let cmd = format!("del {}", id_ref);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupRemLunFail(id_ref.clone()))?;
+            .map_err(|e| fail_context(e, ScstError::GroupRemLunFail(id_ref.clone())))?;
 
         self.luns.remove(&name);
 
@@ -721,6 +1628,18 @@ impl IniGroup {
         &self.initiators
     }
 
+    /// the group holds the initiator, IQNs are compared case-insensitively.
+    pub fn contains_initiator<S: AsRef<str>>(&self, initiator: S) -> bool {
+        self.position_initiator(initiator.as_ref()).is_some()
+    }
+
+    fn position_initiator(&self, initiator: &str) -> Option<usize> {
+        let ini = normalize_initiator(initiator);
+        self.initiators
+            .iter()
+            .position(|item| normalize_initiator(item) == ini)
+    }
+
     /// add an initiator for target initiator group.
     ///
     /// ```no_run
@@ -733,17 +1652,17 @@ impl IniGroup {
     /// group.add_initiator("iqn.1988-12.com.oracle:d4ebaa45254")?;
     /// ```
     pub fn add_initiator<S: AsRef<str>>(&mut self, initiator: S) -> Result<()> {
-        let ini = initiator.as_ref();
-        if self.initiators.contains(&ini.to_string()) {
-            anyhow::bail!(ScstError::GroupIniExists(ini.to_string()))
+        let ini = normalize_initiator(initiator);
+        if self.contains_initiator(&ini) {
+            anyhow::bail!(ScstError::GroupIniExists(ini))
         }
 
         let root = self.root().join(TARGET_INITIATOR);
         let cmd = format!("add {}", ini);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupAddIniFail(ini.to_string()))?;
+            .map_err(|e| fail_context(e, ScstError::GroupAddIniFail(ini.clone())))?;
 
-        self.initiators.push(ini.to_string());
+        self.initiators.push(ini);
 
         Ok(())
     }
@@ -760,19 +1679,18 @@ impl IniGroup {
     /// group.del_initiator("iqn.1988-12.com.oracle:d4ebaa45254")?;
     /// ```
     pub fn del_initiator<S: AsRef<str>>(&mut self, initiator: S) -> Result<()> {
-        let ini = initiator.as_ref();
-        if !self.initiators.contains(&ini.to_string()) {
-            anyhow::bail!(ScstError::GroupNoIni(ini.to_string()))
-        }
+        let index = self
+            .position_initiator(initiator.as_ref())
+            .context(ScstError::GroupNoIni(initiator.as_ref().to_string()))?;
+        // deletes by the name SCST holds, which may differ in case
+        let ini = self.initiators[index].clone();
 
         let root = self.root().join(TARGET_INITIATOR);
         let cmd = format!("del {}", ini);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupRemIniFail(ini.to_string()))?;
+            .map_err(|e| fail_context(e, ScstError::GroupRemIniFail(ini.to_string())))?;
 
-        if let Some(index) = self.initiators.iter().position(|item| *item == ini) {
-            self.initiators.remove(index);
-        }
+        self.initiators.remove(index);
 
         Ok(())
     }
@@ -789,20 +1707,85 @@ impl IniGroup {
     /// group.move_initiator("test1", "iqn.1988-12.com.oracle:d4ebaa45254")?;
     /// ```
     pub fn move_initiator<S: AsRef<str>>(&mut self, initiator: S, dest_group: S) -> Result<()> {
-        let ini = initiator.as_ref().to_string();
+        let index = self
+            .position_initiator(initiator.as_ref())
+            .context(ScstError::GroupNoIni(initiator.as_ref().to_string()))?;
+        // moves by the name SCST holds, which may differ in case
+        let ini = self.initiators[index].clone();
         let group = dest_group.as_ref();
-        if !self.initiators.contains(&ini) {
-            anyhow::bail!(ScstError::GroupNoIni(ini))
-        }
 
         let root = self.root().join(TARGET_INITIATOR);
         let cmd = format!("move {} {}", ini, group);
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupMoveIniFail(ini))?;
+            .map_err(|e| fail_context(e, ScstError::GroupMoveIniFail(ini)))?;
+
+        self.initiators.remove(index);
+
+        Ok(())
+    }
+
+    /// adds the initiators one by one, the failed ones don't stop the others.
+    pub fn add_initiators(&mut self, initiators: &[impl AsRef<str>]) -> BatchResult<String> {
+        let mut batch = BatchResult::new();
+        for ini in initiators {
+            let res = self.add_initiator(ini.as_ref());
+            batch.push(ini.as_ref().to_string(), res);
+        }
+
+        batch
+    }
+
+    /// set the initiators of initiator group to `desired`. Only the missing initiators are
+    /// added and the extra ones deleted, initiators in both sets are left untouched.
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use scst::Scst;
+    ///
+    /// fn main() -> Result<()> {
+    ///     let mut scst = Scst::init()?;
+    ///
+    ///     let target = scst.iscsi_mut().get_target_mut("iqn.2018-11.com.vine:test")?;
+    ///     let group = target.get_ini_group_mut("test")?;
+    ///     group.set_initiators(&["iqn.1988-12.com.oracle:d4ebaa45254"])?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_initiators(&mut self, desired: &[impl AsRef<str>]) -> Result<()> {
+        let desired = desired.iter().map(|d| d.as_ref()).collect::<Vec<&str>>();
+        self.reconcile_initiators(&desired)?;
 
         Ok(())
     }
 
+    /// like `set_initiators()`, but returns the number of initiators (added, removed).
+    pub fn reconcile_initiators(&mut self, desired: &[&str]) -> Result<(usize, usize)> {
+        let extra = self
+            .initiators
+            .iter()
+            .filter(|ini| {
+                let ini = normalize_initiator(ini);
+                !desired.iter().any(|d| normalize_initiator(d) == ini)
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        let removed = extra.len();
+        for ini in extra {
+            self.del_initiator(ini)?;
+        }
+
+        let mut added = 0;
+        for ini in desired {
+            if !self.contains_initiator(ini) {
+                self.add_initiator(ini)?;
+                added += 1;
+            }
+        }
+
+        Ok((added, removed))
+    }
+
     /// clear all initiators to initiator group.
     ///
     /// ```no_run
@@ -818,7 +1801,35 @@ impl IniGroup {
         let root: std::path::PathBuf = self.root().join(TARGET_INITIATOR);
         let cmd = "clear";
         self.mgmt(root, cmd.into())
-            .map_err(|_| ScstError::GroupClearIniFail)?;
+            .map_err(|e| fail_context(e, ScstError::GroupClearIniFail))?;
+
+        Ok(())
+    }
+
+    /// reads the attribute of initiator group, like `io_grouping_type`.
+    pub fn attribute<S: AsRef<str>>(&self, attr: S) -> Result<String> {
+        let attr = attr.as_ref();
+        if attr == "mgmt" || !self.root().join(attr).is_file() {
+            anyhow::bail!(ScstError::GroupBadAttrs)
+        }
+
+        read_fl(self.root().join(attr))
+    }
+
+    /// sets the attribute of initiator group by writing its sysfs file.
+    /// `ScstError::GroupAttrStatic` returns for a read-only attribute.
+    pub fn set_attribute<S: AsRef<str>>(&mut self, attr: S, value: S) -> Result<()> {
+        let attr = attr.as_ref();
+        let path = self.root().join(attr);
+        if attr == "mgmt" || !path.is_file() {
+            anyhow::bail!(ScstError::GroupBadAttrs)
+        }
+        if is_static_attr(&path) {
+            anyhow::bail!(ScstError::GroupAttrStatic(attr.to_string()))
+        }
+
+        echo(path, value.as_ref().into())
+            .map_err(|e| fail_context(e, ScstError::GroupSetAttrFail(attr.to_string())))?;
 
         Ok(())
     }
@@ -860,6 +1871,142 @@ impl Layer for IniGroup {
     }
 }
 
+/// checks the `allowed_portal` value is an IPv4 or IPv6 address. SCST also accepts the
+/// wildcards `*` and `?` in the portal, such a pattern is only checked for the address chars.
+fn check_portal(portal: &str) -> Result<()> {
+    let valid = if portal.contains(['*', '?']) {
+        portal
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || ".:*?[]".contains(c))
+    } else {
+        parse_ip(portal).is_some()
+    };
+    if !valid {
+        anyhow::bail!(ScstError::TargetBadPortal(portal.to_string()))
+    }
+
+    Ok(())
+}
+
+/// checks the writable LUN isn't requested over a read-only device, the device is resolved
+/// under the scst root. Giving `read_only` explicitly acknowledges the device state.
+fn check_lun_read_only(scst_root: Option<&Path>, device: &str, options: &Options) -> Result<()> {
+    let read_only = scst_root
+        .and_then(|root| read_bool(root.join(SCST_DEVICE).join(device).join("read_only")).ok())
+        .unwrap_or(false);
+
+    if read_only && options.contains_keys(&["read_only".to_string()]).is_empty() {
+        anyhow::bail!(ScstError::LunReadOnlyDevice(device.to_string()))
+    }
+
+    Ok(())
+}
+
+/// lowercases an iSCSI qualified name, which is case-insensitive. Other initiator names, like
+/// the FC port names or wildcards, are kept as is.
+pub fn normalize_initiator<S: AsRef<str>>(initiator: S) -> String {
+    let ini = initiator.as_ref();
+    if ini.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("iqn.")) {
+        return ini.to_lowercase();
+    }
+
+    ini.to_string()
+}
+
+/// finds the most specific cause of a failed LUN creation under `luns_root`: the error mapped
+/// from the errno, a missing or inactive device, or a LUN id taken behind the loaded tree.
+fn add_lun_cause(
+    e: &anyhow::Error,
+    scst_root: Option<&Path>,
+    luns_root: &Path,
+    device: &str,
+    lun_id: &str,
+) -> Option<ScstError> {
+    let in_group =
+        luns_root.ancestors().nth(2).and_then(|p| p.file_name()) == Some(OsStr::new(TARGET_GROUP));
+    match e.downcast_ref::<ScstError>() {
+        Some(ScstError::NoDevice(name)) => return Some(ScstError::NoDevice(name.clone())),
+        Some(ScstError::TargetLunExists(_)) => {
+            return Some(ScstError::TargetLunExists(lun_id.to_string()));
+        }
+        Some(ScstError::GroupLunExists(_)) => {
+            return Some(ScstError::GroupLunExists(lun_id.to_string()));
+        }
+        Some(ScstError::LunBadAttrs) => return Some(ScstError::LunBadAttrs),
+        _ => {}
+    }
+
+    let handlers = scst_root.and_then(|root| read_dir(root.join("handlers")).ok());
+    if let Some(handlers) = handlers {
+        let device_root = handlers
+            .filter_map(|res| res.ok())
+            .map(|entry| entry.path().join(device))
+            .find(|path| path.is_dir());
+        match device_root {
+            None => return Some(ScstError::NoDevice(device.to_string())),
+            Some(path) if read_bool(path.join("active")).is_ok_and(|a| !a) => {
+                return Some(ScstError::DeviceNotActive(device.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    if luns_root.join(lun_id).exists() {
+        if in_group {
+            return Some(ScstError::GroupLunExists(lun_id.to_string()));
+        }
+        return Some(ScstError::TargetLunExists(lun_id.to_string()));
+    }
+
+    None
+}
+
+/// refuses to delete the only LUN exporting `device` while the target has active sessions,
+/// the exports are counted by the `exported` directory of the device. The LUN SCST adds to
+/// copy_manager_tgt for every device isn't a path of initiators, so it isn't counted.
+fn check_last_path(scst_root: Option<&Path>, target_root: &Path, device: &str) -> Result<()> {
+    let exported = match scst_root {
+        Some(root) => root.join(SCST_DEVICE).join(device).join("exported"),
+        None => return Ok(()),
+    };
+    let exports = match read_dir(exported) {
+        Ok(entries) => entries
+            .filter_map(|res| res.ok())
+            .filter(|entry| !is_copy_manager_export(&entry.path()))
+            .count(),
+        Err(_) => return Ok(()),
+    };
+    let sessions = read_dir(target_root.join(TARGET_SESSION))
+        .map(|entries| {
+            entries
+                .filter_map(|res| res.ok())
+                .filter(|entry| entry.path().is_dir())
+                .count()
+        })
+        .unwrap_or(0);
+
+    if exports <= 1 && sessions > 0 {
+        anyhow::bail!(ScstError::LastPathToDevice(device.to_string()))
+    }
+
+    Ok(())
+}
+
+// an entry of `devices/<dev>/exported` linking to a LUN of copy_manager_tgt
+fn is_copy_manager_export(export: &Path) -> bool {
+    let link = match read_link(export) {
+        Ok(link) => link,
+        Err(_) => return false,
+    };
+    let parts = link
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    parts
+        .windows(2)
+        .any(|w| w[0] == "copy_manager" && w[1] == "copy_manager_tgt")
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Lun {
     #[serde(skip)]
@@ -867,6 +2014,8 @@ pub struct Lun {
     id: u64,
     device: String,
     read_only: i8,
+    #[serde(default)]
+    alias: Option<String>,
 }
 
 impl Lun {
@@ -885,6 +2034,41 @@ impl Lun {
     pub fn read_only(&self) -> bool {
         self.read_only == 1
     }
+
+    /// the options to add the LUN again, `read_only` is the only one taken by `add`.
+    pub(crate) fn add_options(&self) -> Options {
+        let mut options = Options::new();
+        if self.read_only() {
+            options.insert("read_only", "1");
+        }
+        options
+    }
+
+    /// the friendly name of the LUN, None if it's unset or unsupported by the scst version.
+    pub fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    /// sets the friendly name of the LUN.
+    pub fn set_alias<S: AsRef<str>>(&mut self, alias: S) -> Result<()> {
+        let alias = alias.as_ref();
+        echo(self.root().join("alias"), alias.into())
+            .map_err(|e| fail_context(e, ScstError::LunSetAttrFail("alias".to_string())))?;
+
+        self.alias = Some(alias.to_string());
+        Ok(())
+    }
+
+    /// sets the LUN read-only or writable. SCST may only apply the change to the initiators
+    /// after the LUN is exported again, like by deleting and adding it.
+    pub fn set_read_only(&mut self, ro: bool) -> Result<()> {
+        let value = if ro { "1" } else { "0" };
+        echo(self.root().join("read_only"), value.into())
+            .map_err(|e| fail_context(e, ScstError::LunSetAttrFail("read_only".to_string())))?;
+
+        self.read_only = ro as i8;
+        Ok(())
+    }
 }
 
 impl Layer for Lun {
@@ -906,7 +2090,10 @@ impl Layer for Lun {
             .unwrap_or(OsStr::new(""))
             .to_string_lossy()
             .to_string();
-        self.read_only = read_fl(root_ref.join("read_only"))?.parse::<i8>()?;
+        self.read_only = read_bool(root_ref.join("read_only"))? as i8;
+        self.alias = read_fl(root_ref.join("alias"))
+            .ok()
+            .filter(|alias| !alias.is_empty());
 
         Ok(())
     }
@@ -915,12 +2102,1325 @@ impl Layer for Lun {
 #[cfg(test)]
 mod test {
     use anyhow::Result;
-    use regex::Regex;
+
+    use super::*;
+    use crate::testutil::{Fixture, echoed, echoed_cmds};
 
     #[test]
     fn read_ips() -> Result<()> {
-        let re = Regex::new(r"^(?:\d{1,3}\.){3}\d{1,3}$")?;
-        assert!(re.is_match("192.168.2.30"));
+        assert!(check_portal("192.168.2.30").is_ok());
+        assert!(check_portal("fe80::1").is_ok());
+        assert!(check_portal("[fe80::1]").is_ok());
+        assert!(check_portal("192.168.2.*").is_ok());
+        assert!(check_portal("192.168.2.300").is_err());
+        assert!(check_portal("fe80::1::2").is_err());
+        assert!(check_portal("portal.*").is_err());
+
+        let fx = Fixture::new("read_ips");
+        fx.driver("iscsi").target("iscsi", "iqn.2018-11.com.vine:a");
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/iscsi"))?;
+
+        let err = driver
+            .add_target_attribute("iqn.2018-11.com.vine:a", "allowed_portal", "192.168.2")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::TargetBadPortal(_))
+        ));
+        driver.add_target_attribute("iqn.2018-11.com.vine:a", "allowed_portal", "fe80::1")?;
+        assert_eq!(
+            echoed_cmds(),
+            vec!["add_target_attribute iqn.2018-11.com.vine:a allowed_portal fe80::1"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_initiators() -> Result<()> {
+        let fx = Fixture::new("set_initiators");
+        fx.group("tgt", "grp", &["iqn.a", "iqn.b"]);
+
+        let mut group = IniGroup::default();
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+        group.set_initiators(&["iqn.a", "iqn.c"])?;
+
+        assert_eq!(echoed_cmds(), vec!["del iqn.b", "add iqn.c"]);
+        assert_eq!(group.initiators(), &["iqn.a", "iqn.c"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_initiators() -> Result<()> {
+        let fx = Fixture::new("reconcile_initiators");
+        fx.group("tgt", "grp", &["iqn.a", "iqn.b"]);
+
+        let mut group = IniGroup::default();
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+
+        assert_eq!(group.reconcile_initiators(&["iqn.a", "iqn.c"])?, (1, 1));
+        assert_eq!(echoed_cmds(), vec!["del iqn.b", "add iqn.c"]);
+
+        assert_eq!(group.reconcile_initiators(&["iqn.a", "iqn.c"])?, (0, 0));
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_over_read_only_device() -> Result<()> {
+        let fx = Fixture::new("add_lun_over_read_only_device");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[])
+            .file("devices/vol/read_only", "1\n");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let err = target.add_lun("vol", 0, &Options::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::LunReadOnlyDevice(dev)) if dev == "vol"
+        ));
+        let group = target.get_ini_group_mut("grp")?;
+        let err = group.add_lun("vol", 0, &Options::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::LunReadOnlyDevice(_))
+        ));
+        assert!(echoed_cmds().is_empty());
+
+        fx.lun(tgt, 0, "vol");
+        let mut options = Options::new();
+        options.insert("read_only", "1");
+        target.add_lun("vol", 0, &options)?;
+        assert_eq!(echoed_cmds(), vec!["add vol 0 read_only=1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tpg_id() -> Result<()> {
+        let fx = Fixture::new("tpg_id");
+        fx.target("iscsi", "iqn.2018-11.com.vine:a")
+            .target("iscsi", "iqn.2018-11.com.vine:b")
+            .file("device_groups/dg/target_groups/tg/group_id", "3\n")
+            .file(
+                "device_groups/dg/target_groups/tg/iqn.2018-11.com.vine:a/rel_tgt_id",
+                "1\n",
+            );
+
+        let mut target = Target::default();
+        target.load(fx.path("targets/iscsi/iqn.2018-11.com.vine:a"))?;
+        assert_eq!(target.tpg_id()?, Some(3));
+
+        let mut target = Target::default();
+        target.load(fx.path("targets/iscsi/iqn.2018-11.com.vine:b"))?;
+        assert_eq!(target.tpg_id()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_tpg() -> Result<()> {
+        let fx = Fixture::new("set_tpg");
+        fx.target("iscsi", "iqn.2018-11.com.vine:a")
+            .file("device_groups/dg/target_groups/tg/group_id", "3\n");
+
+        let mut tpg = TargetGroup::default();
+        tpg.load(fx.path("device_groups/dg/target_groups/tg"))?;
+        let mut target = Target::default();
+        target.load(fx.path("targets/iscsi/iqn.2018-11.com.vine:a"))?;
+        target.set_tpg(&tpg)?;
+
+        let echoed = echoed();
+        assert_eq!(echoed.len(), 1);
+        assert_eq!(
+            echoed[0].0,
+            fx.path("device_groups/dg/target_groups/tg/mgmt")
+        );
+        assert_eq!(echoed[0].1, "add iqn.2018-11.com.vine:a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn cached_sessions() -> Result<()> {
+        let fx = Fixture::new("cached_sessions");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:a";
+        fx.target("iscsi", "iqn.2018-11.com.vine:a")
+            .session(tgt, "iqn.1988-12.com.oracle:a", "1");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert!(target.cached_sessions().is_empty());
+
+        target.refresh_sessions()?;
+        assert_eq!(target.cached_sessions().len(), 1);
+
+        fx.session(tgt, "iqn.1988-12.com.oracle:b", "2");
+        assert_eq!(target.cached_sessions().len(), 1);
+        assert_eq!(target.sessions()?.len(), 2);
+
+        target.refresh_sessions()?;
+        assert_eq!(target.cached_sessions().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_wait() -> Result<()> {
+        let fx = Fixture::new("add_lun_wait");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .device("vdisk_blockio", "vol", "/dev/zvol/tank/vol")
+            .file("handlers/vdisk_blockio/vol/active", "0\n");
+
+        let mut device = Device::default();
+        device.load(fx.path("handlers/vdisk_blockio/vol"))?;
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let timeout = Duration::from_millis(10);
+        assert!(
+            target
+                .add_lun_wait(&device, 0, &Options::new(), timeout)
+                .is_err()
+        );
+        assert!(echoed_cmds().is_empty());
+
+        fx.file("handlers/vdisk_blockio/vol/active", "1\n")
+            .lun(tgt, 0, "vol");
+        target.add_lun_wait(&device, 0, &Options::new(), timeout)?;
+        assert_eq!(echoed_cmds(), vec!["add vol 0"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn create_ini_group_with() -> Result<()> {
+        let fx = Fixture::new("create_ini_group_with");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        // the kernel creates the group, but rejects the initiator
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.group(tgt, "grp", &[])
+            .dir(format!("{}/initiators/mgmt", grp));
+
+        let err = target
+            .create_ini_group_with("grp", &[], &["iqn.a", "iqn.b"])
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupAddIniFail(ini)) if ini == "iqn.a"
+        ));
+        assert_eq!(echoed_cmds(), vec!["create grp", "add iqn.a", "del grp"]);
+        assert!(target.get_ini_group("grp").is_err());
+
+        fx.remove(format!("{}/initiators/mgmt", grp));
+        let group = target.create_ini_group_with("grp", &[], &["iqn.a", "iqn.b"])?;
+        assert_eq!(group.initiators(), &["iqn.a", "iqn.b"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_extra() -> Result<()> {
+        let fx = Fixture::new("target_load_extra");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .file(format!("{}/addr_method", tgt), "PERIPHERAL\n")
+            .file(format!("{}/HeaderDigest", tgt), "None\n[key]\n")
+            .file(format!("{}/mgmt", tgt), "Usage: ...\n")
+            .link(format!("{}/driver", tgt), fx.path("targets/iscsi"));
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let extra = target.extra();
+        assert_eq!(
+            extra.get("addr_method").map(|v| v.as_str()),
+            Some("PERIPHERAL")
+        );
+        assert_eq!(extra.get("HeaderDigest").map(|v| v.as_str()), Some("None"));
+        for name in [
+            "tid",
+            "rel_tgt_id",
+            "enabled",
+            "mgmt",
+            "driver",
+            "luns",
+            "sessions",
+        ] {
+            assert!(!extra.contains_key(name), "{} in extra", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn del_last_path_to_device() -> Result<()> {
+        let fx = Fixture::new("del_last_path_to_device");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .group(tgt, "grp", &[])
+            .lun(&grp, 1, "vol2")
+            .lun("targets/copy_manager/copy_manager_tgt", 0, "vol1")
+            .lun("targets/copy_manager/copy_manager_tgt", 1, "vol2")
+            .link(
+                "devices/vol1/exported/export0",
+                fx.path(format!("{}/luns/0", tgt)),
+            )
+            .link(
+                "devices/vol1/exported/export1",
+                "../../../targets/copy_manager/copy_manager_tgt/luns/0",
+            )
+            .link(
+                "devices/vol2/exported/export0",
+                fx.path(format!("{}/luns/1", grp)),
+            )
+            .link(
+                "devices/vol2/exported/export1",
+                fx.path(format!("{}/luns/0", tgt)),
+            )
+            .link(
+                "devices/vol2/exported/export2",
+                "../../../targets/copy_manager/copy_manager_tgt/luns/1",
+            )
+            .session(tgt, "iqn.1991-05.com.microsoft:host", "10000");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let err = target.del_lun(0).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::LastPathToDevice(dev)) if dev == "vol1"
+        ));
+        assert!(echoed_cmds().is_empty());
+        assert!(target.get_lun("lun0").is_ok());
+
+        // vol2 is exported by another LUN as well
+        target.get_ini_group_mut("grp")?.del_lun(1)?;
+        assert_eq!(echoed_cmds(), vec!["del 1"]);
+
+        target.del_lun_force(0)?;
+        assert_eq!(echoed_cmds(), vec!["del 0"]);
+        assert!(target.get_lun("lun0").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn enabled_transitions() -> Result<()> {
+        let fx = Fixture::new("enabled_transitions");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert_eq!(target.last_change(), None);
+        assert!(target.transitions().is_empty());
+
+        let before = SystemTime::now();
+        target.disable()?;
+        target.enable()?;
+
+        let transitions = target.transitions();
+        assert_eq!(transitions.len(), 2);
+        assert!(!transitions[0].enabled());
+        assert!(transitions[1].enabled());
+        assert!(transitions[0].at() >= before);
+        assert!(transitions[1].at() >= transitions[0].at());
+
+        fx.file(format!("{}/last_change", tgt), "1700000000\n");
+        target.load(fx.path(tgt))?;
+        assert_eq!(target.last_change(), Some("1700000000"));
+        assert_eq!(target.transitions().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn close_stale_sessions() -> Result<()> {
+        let fx = Fixture::new("close_stale_sessions");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let now = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .session(tgt, "iqn.old", "1")
+            .file(
+                format!("{}/sessions/iqn.old/login_time", tgt),
+                &format!("{}\n", now - 7200),
+            )
+            .session(tgt, "iqn.young", "2")
+            .file(
+                format!("{}/sessions/iqn.young/login_time", tgt),
+                &format!("{}\n", now - 10),
+            )
+            .session(tgt, "iqn.unknown", "3");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let closed = target.close_stale_sessions(Duration::from_secs(3600))?;
+        assert_eq!(closed, 1);
+        assert_eq!(
+            echoed(),
+            vec![(
+                fx.path(format!("{}/sessions/iqn.old/force_close", tgt)),
+                "1".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn lun_alias() -> Result<()> {
+        let fx = Fixture::new("lun_alias");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .file(format!("{}/luns/0/alias", tgt), "database\n")
+            .lun(tgt, 1, "vol2");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert_eq!(target.get_lun("lun0")?.alias(), Some("database"));
+        assert_eq!(target.get_lun("lun1")?.alias(), None);
+
+        let lun = target.get_lun_mut("lun1")?;
+        lun.set_alias("logs")?;
+        assert_eq!(lun.alias(), Some("logs"));
+        assert_eq!(
+            echoed(),
+            vec![(fx.path(format!("{}/luns/1/alias", tgt)), "logs".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn lun_set_read_only() -> Result<()> {
+        let fx = Fixture::new("lun_set_read_only");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        let lun = target.get_lun_mut("lun0")?;
+        assert!(!lun.read_only());
+
+        lun.set_read_only(true)?;
+        assert!(lun.read_only());
+        assert_eq!(
+            echoed(),
+            vec![(
+                fx.path(format!("{}/luns/0/read_only", tgt)),
+                "1".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_initiators_batch() -> Result<()> {
+        let fx = Fixture::new("add_initiators_batch");
+        fx.group("tgt", "grp", &["iqn.a"]);
+
+        let mut group = IniGroup::default();
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+
+        let batch = group.add_initiators(&["iqn.a", "iqn.b", "iqn.c"]);
+        assert!(!batch.all_ok());
+        assert_eq!(batch.succeeded(), &["iqn.b", "iqn.c"]);
+        assert_eq!(batch.failed()[0].0, "iqn.a");
+        assert!(matches!(batch.failed()[0].1, ScstError::GroupIniExists(_)));
+        assert_eq!(echoed_cmds(), vec!["add iqn.b", "add iqn.c"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iscsi_params() -> Result<()> {
+        let fx = Fixture::new("iscsi_params");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .file(format!("{}/MaxRecvDataSegmentLength", tgt), "1048576\n")
+            .file(format!("{}/MaxBurstLength", tgt), "1048576\n[key]\n")
+            .file(format!("{}/FirstBurstLength", tgt), "65536\n")
+            .file(format!("{}/MaxOutstandingR2T", tgt), "32\n");
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert_eq!(target.max_recv_data_segment_length(), Some(1048576));
+        assert_eq!(target.max_burst_length(), Some(1048576));
+        assert_eq!(target.first_burst_length(), Some(65536));
+        assert_eq!(target.max_outstanding_r2t(), Some(32));
+        assert!(!target.extra().contains_key("MaxBurstLength"));
+
+        target.set_max_burst_length(262144)?;
+        target.set_max_outstanding_r2t(64)?;
+        assert_eq!(target.max_burst_length(), Some(262144));
+        assert_eq!(target.max_outstanding_r2t(), Some(64));
+        assert_eq!(
+            echoed(),
+            vec![
+                (
+                    fx.path(format!("{}/MaxBurstLength", tgt)),
+                    "262144".to_string()
+                ),
+                (
+                    fx.path(format!("{}/MaxOutstandingR2T", tgt)),
+                    "64".to_string()
+                ),
+            ]
+        );
+
+        for (res, name) in [
+            (
+                target.set_max_recv_data_segment_length(1000),
+                "MaxRecvDataSegmentLength",
+            ),
+            (
+                target.set_max_recv_data_segment_length(256),
+                "MaxRecvDataSegmentLength",
+            ),
+            (
+                target.set_max_recv_data_segment_length(1 << 24),
+                "MaxRecvDataSegmentLength",
+            ),
+            (target.set_first_burst_length(524288), "FirstBurstLength"),
+            (target.set_max_burst_length(32768), "MaxBurstLength"),
+            (target.set_max_outstanding_r2t(0), "MaxOutstandingR2T"),
+            (target.set_max_outstanding_r2t(65536), "MaxOutstandingR2T"),
+        ] {
+            let err = res.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ScstError>(),
+                Some(ScstError::TargetBadParam { name: n, .. }) if n == name
+            ));
+        }
+        assert!(echoed_cmds().is_empty());
+        assert_eq!(target.max_burst_length(), Some(262144));
+
+        fx.target("iscsi", "iqn.2018-11.com.vine:none");
+        let mut target = Target::default();
+        target.load(fx.path("targets/iscsi/iqn.2018-11.com.vine:none"))?;
+        assert_eq!(target.max_burst_length(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_failure_cause() -> Result<()> {
+        let fx = Fixture::new("add_lun_failure_cause");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .file("handlers/vdisk_blockio/vol2/active", "0\n")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[]);
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        // the mgmt files refuse to be written, the causes are found from the tree
+        fx.dir(format!("{}/luns/mgmt", tgt))
+            .dir(format!("{}/luns/mgmt", grp))
+            .lun(tgt, 1, "vol1")
+            .lun(&grp, 1, "vol1");
+
+        let cause = |e: anyhow::Error| ScstError::from_anyhow(e).to_string();
+        let opts = Options::new();
+        let err = target.add_lun("missing", 0, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::NoDevice("missing".to_string()).to_string()
+        );
+        let err = target.add_lun("vol2", 0, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::DeviceNotActive("vol2".to_string()).to_string()
+        );
+        let err = target.add_lun("vol1", 1, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::TargetLunExists("1".to_string()).to_string()
+        );
+        let err = target.add_lun("vol1", 0, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::TargetAddLunFail("0".to_string()).to_string()
+        );
+
+        let group = target.get_ini_group_mut("grp")?;
+        let err = group.add_lun("missing", 0, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::NoDevice("missing".to_string()).to_string()
+        );
+        let err = group.add_lun("vol1", 1, &opts).unwrap_err();
+        assert_eq!(
+            cause(err),
+            ScstError::GroupLunExists("1".to_string()).to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn groups_without_initiator() -> Result<()> {
+        let fx = Fixture::new("groups_without_initiator");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let ini = "iqn.1991-05.com.microsoft:host";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp1", &[])
+            .group(tgt, "grp2", &[ini])
+            .group(tgt, "grp3", &["iqn.1991-05.com.microsoft:other"]);
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let names = target
+            .groups_without_initiator(ini)
+            .iter()
+            .map(|g| g.name())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["grp1", "grp3"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn total_session_count() -> Result<()> {
+        let fx = Fixture::new("total_session_count");
+        let tgt1 = "targets/iscsi/iqn.2018-11.com.vine:vol1";
+        let tgt2 = "targets/iscsi/iqn.2018-11.com.vine:vol2";
+        fx.driver("iscsi")
+            .target("iscsi", "iqn.2018-11.com.vine:vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol2")
+            .session(tgt1, "iqn.1991-05.com.microsoft:a", "1")
+            .session(tgt1, "iqn.1991-05.com.microsoft:b", "2")
+            .session(tgt2, "iqn.1991-05.com.microsoft:c", "3");
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/iscsi"))?;
+
+        assert_eq!(
+            driver
+                .get_target("iqn.2018-11.com.vine:vol1")?
+                .session_count()?,
+            2
+        );
+        assert_eq!(driver.total_session_count()?, 3);
+
+        fx.remove(format!("{}/sessions", tgt2));
+        let err = driver.total_session_count().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::SessionCountFail { counted: 2, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_driver_without_enabled() -> Result<()> {
+        let fx = Fixture::new("load_driver_without_enabled");
+        fx.driver("qla2x00t").remove("targets/qla2x00t/enabled");
+
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/qla2x00t"))?;
+        assert!(!driver.enabled());
+        assert_eq!(driver.warnings().len(), 1);
+        assert!(driver.warnings()[0].contains("enabled"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn describe() -> Result<()> {
+        let fx = Fixture::new("target_describe");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .lun(tgt, 1, "vol2")
+            .group(
+                tgt,
+                "grp",
+                &["iqn.1991-05.com.microsoft:a", "iqn.1991-05.com.microsoft:b"],
+            )
+            .session(tgt, "iqn.1991-05.com.microsoft:a", "1");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let text = target.describe();
+        assert!(text.starts_with("Target: iqn.2018-11.com.vine:vol\n"));
+        assert!(text.contains("  Enabled: yes\n"));
+        assert!(text.contains("  Relative target id: 1\n"));
+        assert!(text.contains("  LUNs: 2\n    lun0: vol1\n    lun1: vol2\n"));
+        assert!(text.contains("  Groups: 1\n    grp: 2 initiators\n"));
+        assert!(text.ends_with("  Sessions: 1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_attribute() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let fx = Fixture::new("group_attribute");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[])
+            .file(format!("{}/io_grouping_type", grp), "auto\n")
+            .file(format!("{}/addr_method", grp), "PERIPHERAL\n");
+        let addr_method = fx.path(format!("{}/addr_method", grp));
+        std::fs::set_permissions(&addr_method, std::fs::Permissions::from_mode(0o444))?;
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        let group = target.get_ini_group_mut("grp")?;
+
+        assert_eq!(group.attribute("io_grouping_type")?, "auto");
+        group.set_attribute("io_grouping_type", "this_group_only")?;
+        assert_eq!(group.attribute("io_grouping_type")?, "this_group_only");
+
+        assert_eq!(group.attribute("addr_method")?, "PERIPHERAL");
+        let err = group.set_attribute("addr_method", "FLAT").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupAttrStatic(a)) if a == "addr_method"
+        ));
+        assert_eq!(group.attribute("addr_method")?, "PERIPHERAL");
+
+        let err = group.attribute("missing").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupBadAttrs)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn move_lun() -> Result<()> {
+        let fx = Fixture::new("move_lun");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .file(format!("{}/luns/0/read_only", tgt), "1\n")
+            .file(format!("{}/luns/0/alias", tgt), "database\n")
+            .lun(tgt, 1, "vol2")
+            .link(
+                "devices/vol1/exported/export0",
+                fx.path(format!("{}/luns/0", tgt)),
+            )
+            .session(tgt, "iqn.1991-05.com.microsoft:host", "10000")
+            .simulate();
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let err = target.move_lun(0, 1).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::TargetLunExists(id)) if id == "1"
+        ));
+
+        // the only LUN of a device in use is renumbered as well
+        target.move_lun(0, 5)?;
+        assert_eq!(
+            echoed_cmds(),
+            vec!["del 0", "add vol1 5 read_only=1", "database"]
+        );
+        assert_eq!(target.get_lun("lun5")?.alias(), Some("database"));
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert!(target.get_lun("lun0").is_err());
+        let lun = target.get_lun("lun5")?;
+        assert_eq!(lun.device(), "vol1");
+        assert!(lun.read_only());
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_failure_source() -> Result<()> {
+        let fx = Fixture::new("add_lun_failure_source");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .dir(format!("{}/luns/mgmt", tgt));
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        let err = target.add_lun("vol1", 1, &Options::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::TargetAddLunFail(id)) if id == "1"
+        ));
+        assert!(
+            err.chain()
+                .any(|cause| cause.downcast_ref::<std::io::Error>().is_some())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_attributes() -> Result<()> {
+        let fx = Fixture::new("target_attributes");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .file(format!("{}/QueuedCommands", tgt), "32\n")
+            .file(format!("{}/DataDigest", tgt), "None\n")
+            .file(format!("{}/IncomingUser", tgt), "joe secret12345\n[key]\n");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        assert_eq!(target.attribute("QueuedCommands"), Some("32"));
+        assert_eq!(target.attribute("DataDigest"), Some("None"));
+        assert_eq!(target.attribute("IncomingUser"), Some("joe secret12345"));
+        assert!(!serde_json::to_string(&target)?.contains("secret12345"));
+        assert_eq!(target.attribute("rel_tgt_id"), Some("1"));
+        assert_eq!(target.attribute("OutgoingUser"), None);
+        for name in ["luns", "ini_groups", "sessions", "mgmt"] {
+            assert!(target.attribute(name).is_none(), "{} in attributes", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn close_session() -> Result<()> {
+        let fx = Fixture::new("close_session");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .session(tgt, "iqn.host1", "0x1")
+            .session(tgt, "iqn.host2", "0x2");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        target.close_session("0x2")?;
+        assert_eq!(
+            echoed(),
+            vec![(
+                fx.path(format!("{}/sessions/iqn.host2/force_close", tgt)),
+                "1".to_string()
+            )]
+        );
+
+        let err = target.close_session("0x9").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::NoSession)
+        ));
+        assert!(echoed().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ensure_lun() -> Result<()> {
+        let fx = Fixture::new("ensure_lun");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .simulate();
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        // matching LUN exists
+        assert_eq!(
+            target.ensure_lun("vol1", 0, &Options::new())?.device(),
+            "vol1"
+        );
+        assert!(echoed().is_empty());
+
+        // conflicting LUN exists
+        let err = target.ensure_lun("vol2", 0, &Options::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::LunDeviceExists(d)) if d == "vol1"
+        ));
+        assert!(echoed().is_empty());
+
+        // missing LUN is created
+        assert_eq!(
+            target.ensure_lun("vol2", 1, &Options::new())?.device(),
+            "vol2"
+        );
+        assert_eq!(
+            echoed(),
+            vec![(
+                fx.path(format!("{}/luns/mgmt", tgt)),
+                "add vol2 1".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_ensure_lun() -> Result<()> {
+        let fx = Fixture::new("group_ensure_lun");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[])
+            .lun(&grp, 0, "vol1")
+            .simulate();
+        let mut group = IniGroup::default();
+        group.load(fx.path(&grp))?;
+
+        assert_eq!(
+            group.ensure_lun("vol1", 0, &Options::new())?.device(),
+            "vol1"
+        );
+        assert!(group.ensure_lun("vol2", 0, &Options::new()).is_err());
+        assert_eq!(
+            group.ensure_lun("vol2", 1, &Options::new())?.device(),
+            "vol2"
+        );
+        assert_eq!(
+            echoed(),
+            vec![(
+                fx.path(format!("{}/luns/mgmt", grp)),
+                "add vol2 1".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn close_all_sessions() -> Result<()> {
+        let fx = Fixture::new("close_all_sessions");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .session(tgt, "iqn.host1", "0x1")
+            .session(tgt, "iqn.host2", "0x2")
+            .dir(format!("{}/sessions/iqn.host2/force_close", tgt));
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        // a failed close doesn't stop the others
+        assert_eq!(target.close_all_sessions()?, 2);
+        assert_eq!(echoed().len(), 2);
+
+        fx.remove(format!("{}/sessions/iqn.host1/force_close", tgt))
+            .dir(format!("{}/sessions/iqn.host1/force_close", tgt));
+        let err = target.close_all_sessions().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::SessionCloseFail)
+        ));
+        echoed();
+
+        fx.remove(format!("{}/sessions/iqn.host1", tgt))
+            .remove(format!("{}/sessions/iqn.host2", tgt));
+        assert_eq!(target.close_all_sessions()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_auto() -> Result<()> {
+        let fx = Fixture::new("add_lun_auto");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .device("vdisk_blockio", "vol3", "/dev/zvol/tank/vol3")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .lun(tgt, 2, "vol2")
+            .simulate();
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        assert_eq!(target.next_free_lun_id(), 1);
+        assert_eq!(target.add_lun_auto("vol3", &Options::new())?, 1);
+        assert_eq!(echoed_cmds(), vec!["add vol3 1"]);
+        assert_eq!(target.next_free_lun_id(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_lun_to_groups() -> Result<()> {
+        let fx = Fixture::new("add_lun_to_groups");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.scst()
+            .device("vdisk_blockio", "vol1", "/dev/zvol/tank/vol1")
+            .device("vdisk_blockio", "vol2", "/dev/zvol/tank/vol2")
+            .target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp1", &[])
+            .group(tgt, "grp2", &[])
+            .simulate();
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        target.add_lun_to_groups("vol1", 0, &["grp1", "grp2"], &Options::new())?;
+        assert_eq!(target.get_lun_by_id(0)?.device(), "vol1");
+        for group in ["grp1", "grp2"] {
+            let group = target.get_ini_group(group)?;
+            assert_eq!(group.get_lun_by_id(0)?.device(), "vol1");
+        }
+        assert_eq!(echoed_cmds(), vec!["add vol1 0"; 3]);
+
+        // the missing group rolls back the added LUNs
+        assert!(
+            target
+                .add_lun_to_groups("vol2", 1, &["grp1", "missing"], &Options::new())
+                .is_err()
+        );
+        assert!(target.get_lun_by_id(1).is_err());
+        assert!(target.get_ini_group("grp1")?.get_lun_by_id(1).is_err());
+        assert_eq!(
+            echoed_cmds(),
+            vec!["add vol2 1", "add vol2 1", "del 1", "del 1"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_target_with_tid() -> Result<()> {
+        let fx = Fixture::new("add_target_with_tid");
+        fx.scst()
+            .file(
+                "targets/iscsi/mgmt",
+                "Usage: echo \"add_target target_name [parameters]\" >mgmt\n\
+                 The following target attributes available: IncomingUser, tid\n",
+            )
+            .simulate();
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/iscsi"))?;
+
+        driver.add_target_with_tid("iqn.2018-11.com.vine:vol", 7, &Options::new())?;
+        assert_eq!(
+            echoed_cmds(),
+            vec!["add_target iqn.2018-11.com.vine:vol tid=7"]
+        );
+
+        fx.file(
+            "targets/iscsi/mgmt",
+            "The following target attributes available: IncomingUser\n",
+        );
+        let err = driver
+            .add_target_with_tid("iqn.2018-11.com.vine:vol2", 8, &Options::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::DriverNotVirt)
+        ));
+        assert!(echoed_cmds().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_lun_by_id() -> Result<()> {
+        let fx = Fixture::new("get_lun_by_id");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let grp = format!("{}/ini_groups/grp", tgt);
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .group(tgt, "grp", &[])
+            .lun(&grp, 3, "vol2");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        assert_eq!(target.get_lun_by_id(0)?.device(), "vol1");
+        assert_eq!(target.get_lun_by_id_mut(0)?.id(), 0);
+        assert!(matches!(
+            target.get_lun_by_id(1).unwrap_err().downcast_ref::<ScstError>(),
+            Some(ScstError::TargetNoLun(id)) if id == "lun1"
+        ));
+
+        let group = target.get_ini_group_mut("grp")?;
+        assert_eq!(group.get_lun_by_id(3)?.device(), "vol2");
+        assert!(group.get_lun_by_id_mut(0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn initiator_case_insensitive() -> Result<()> {
+        let fx = Fixture::new("initiator_case_insensitive");
+        fx.group("tgt", "grp", &[]);
+
+        let mut group = IniGroup::default();
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+        group.add_initiator("iqn.2018-11.Com.Vine:x")?;
+        let err = group.add_initiator("iqn.2018-11.com.vine:x").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupIniExists(_))
+        ));
+        assert_eq!(group.initiators(), &["iqn.2018-11.com.vine:x"]);
+        assert!(group.contains_initiator("IQN.2018-11.COM.VINE:X"));
+        assert_eq!(echoed_cmds(), vec!["add iqn.2018-11.com.vine:x"]);
+
+        group.del_initiator("iqn.2018-11.COM.vine:x")?;
+        assert!(group.initiators().is_empty());
+        assert_eq!(echoed_cmds(), vec!["del iqn.2018-11.com.vine:x"]);
+
+        // SCST holds the name as written by others, it's moved by that name
+        fx.file("tgt/ini_groups/grp/initiators/iqn.2018-11.Com.Vine:y", "");
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+        group.move_initiator("IQN.2018-11.com.vine:Y", "other")?;
+        assert!(group.initiators().is_empty());
+        assert_eq!(echoed_cmds(), vec!["move iqn.2018-11.Com.Vine:y other"]);
+
+        assert_eq!(
+            normalize_initiator("21:00:00:E0:8B:05:05:04"),
+            "21:00:00:E0:8B:05:05:04"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mgmt_errno_outermost() -> Result<()> {
+        let fx = Fixture::new("mgmt_errno_outermost");
+        fx.group("tgt", "grp", &["iqn.2018-11.com.vine:x"]);
+
+        let mut group = IniGroup::default();
+        group.load(fx.path("tgt/ini_groups/grp"))?;
+        // the initiator is gone behind the loaded tree, writing mgmt fails with ENOENT
+        fx.remove("tgt/ini_groups/grp/initiators");
+        let err = group.del_initiator("iqn.2018-11.com.vine:x").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupNoIni(ini)) if ini == "iqn.2018-11.com.vine:x"
+        ));
+
+        // an unmapped errno is wrapped in the generic failure
+        fx.dir("tgt/ini_groups/grp/initiators/mgmt");
+        let err = group.del_initiator("iqn.2018-11.com.vine:x").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::GroupRemIniFail(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_open_access() -> Result<()> {
+        let fx = Fixture::new("is_open_access");
+        let open = "targets/iscsi/iqn.2018-11.com.vine:open";
+        let chap = "targets/iscsi/iqn.2018-11.com.vine:chap";
+        let acl = "targets/iscsi/iqn.2018-11.com.vine:acl";
+        // protected by the CHAP user of its driver only
+        let drv = "targets/isert/iqn.2018-11.com.vine:drv";
+        fx.target("iscsi", "iqn.2018-11.com.vine:open")
+            .lun(open, 0, "vol1")
+            .file(format!("{}/IncomingUser", open), "\n")
+            .target("iscsi", "iqn.2018-11.com.vine:chap")
+            .lun(chap, 0, "vol1")
+            .file(format!("{}/IncomingUser", chap), "joe secret12345\n[key]\n")
+            .target("iscsi", "iqn.2018-11.com.vine:acl")
+            .group(acl, "grp", &["iqn.a"])
+            .lun(&format!("{}/ini_groups/grp", acl), 0, "vol1")
+            .target("isert", "iqn.2018-11.com.vine:drv")
+            .lun(drv, 0, "vol1")
+            .file("targets/isert/IncomingUser", "joe secret12345\n[key]\n");
+
+        let cases = [(open, true), (chap, false), (acl, false), (drv, false)];
+        for (path, expected) in cases {
+            let mut target = Target::default();
+            target.load(fx.path(path))?;
+            assert_eq!(target.is_open_access()?, expected, "{}", path);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_all_luns_read_only() -> Result<()> {
+        let fx = Fixture::new("set_all_luns_read_only");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .lun(tgt, 1, "vol2")
+            .lun(tgt, 2, "vol3");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        assert_eq!(target.set_all_luns_read_only(true)?, 3);
+        assert!(target.luns().iter().all(|lun| lun.read_only()));
+        assert_eq!(echoed_cmds(), vec!["1", "1", "1"]);
+
+        // already read-only, nothing changes
+        assert_eq!(target.set_all_luns_read_only(true)?, 0);
+        assert!(echoed_cmds().is_empty());
+
+        assert_eq!(target.set_all_luns_read_only(false)?, 3);
+        assert!(target.luns().iter().all(|lun| !lun.read_only()));
+        assert_eq!(echoed_cmds(), vec!["0", "0", "0"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_lun() -> Result<()> {
+        let fx = Fixture::new("migrate_lun");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .lun(tgt, 0, "vol1")
+            .session(tgt, "iqn.1994-05.com.redhat:client", "1")
+            .file(
+                format!(
+                    "{}/sessions/iqn.1994-05.com.redhat:client/active_commands",
+                    tgt
+                ),
+                "0\n",
+            )
+            .simulate();
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        target.migrate_lun(0, "vol2", Duration::from_secs(1))?;
+        assert_eq!(
+            echoed_cmds(),
+            vec!["replace vol1 0 read_only=1", "replace vol2 0"]
+        );
+        let lun = target.get_lun_by_id(0)?;
+        assert_eq!(lun.device(), "vol2");
+        assert!(!lun.read_only());
+
+        // commands never drain, the LUN is made writable again
+        fx.file(
+            format!(
+                "{}/sessions/iqn.1994-05.com.redhat:client/active_commands",
+                tgt
+            ),
+            "2\n",
+        );
+        let err = target
+            .migrate_lun(0, "vol3", Duration::from_millis(0))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScstError>(),
+            Some(ScstError::LunDrainTimeout(_))
+        ));
+        assert_eq!(
+            echoed_cmds(),
+            vec!["replace vol2 0 read_only=1", "replace vol2 0"]
+        );
+        let lun = target.get_lun_by_id(0)?;
+        assert_eq!(lun.device(), "vol2");
+        assert!(!lun.read_only());
+
+        // a read-only LUN is replaced as is
+        fx.file(
+            format!(
+                "{}/sessions/iqn.1994-05.com.redhat:client/active_commands",
+                tgt
+            ),
+            "0\n",
+        )
+        .file(format!("{}/luns/0/read_only", tgt), "1\n");
+        target.load(fx.path(tgt))?;
+        target.migrate_lun(0, "vol3", Duration::from_secs(1))?;
+        assert_eq!(echoed_cmds(), vec!["replace vol3 0 read_only=1"]);
+        assert!(target.get_lun_by_id(0)?.read_only());
+
+        Ok(())
+    }
+
+    #[test]
+    fn idle_initiators() -> Result<()> {
+        let fx = Fixture::new("idle_initiators");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp1", &["iqn.host1", "iqn.host2"])
+            .group(tgt, "grp2", &["iqn.host3"])
+            .session(tgt, "iqn.host1", "0x1")
+            .session(tgt, "iqn.host3", "0x3");
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+
+        assert_eq!(target.idle_initiators()?, vec!["iqn.host2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mgmt_help() -> Result<()> {
+        let fx = Fixture::new("target_mgmt_help");
+        let tgt = "targets/iscsi/iqn.2018-11.com.vine:vol";
+        let usage = "Usage: echo \"add H:C:I:L lun [parameters]\" >mgmt\n";
+        fx.target("iscsi", "iqn.2018-11.com.vine:vol")
+            .group(tgt, "grp", &[])
+            .file(format!("{}/luns/mgmt", tgt), usage)
+            .file(format!("{}/ini_groups/grp/luns/mgmt", tgt), usage);
+
+        let mut target = Target::default();
+        target.load(fx.path(tgt))?;
+        assert_eq!(target.mgmt_help()?, usage);
+        assert_eq!(target.get_ini_group("grp")?.mgmt_help()?, usage);
+
+        fx.remove(format!("{}/luns/mgmt", tgt));
+        assert!(target.mgmt_help().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn driver_attributes() -> Result<()> {
+        let fx = Fixture::new("driver_attributes");
+        fx.driver("iscsi")
+            .file("targets/iscsi/iSNSServer", "10.0.0.1\n")
+            .file("targets/iscsi/IncomingUser", "joe secret12345\n[key]\n")
+            .file("targets/iscsi/mgmt", "");
+        let mut driver = Driver::default();
+        driver.load(fx.path("targets/iscsi"))?;
+
+        assert_eq!(driver.attribute("iSNSServer"), Some("10.0.0.1"));
+        assert_eq!(driver.attribute("IncomingUser"), Some("joe secret12345"));
+        assert!(!serde_json::to_string(&driver)?.contains("secret12345"));
+        assert_eq!(driver.attribute("open_state"), Some("open"));
+        assert_eq!(driver.attribute("mgmt"), None);
+
+        driver.add_attribute("IncomingUser", "ann secret67890")?;
+        assert_eq!(driver.attribute("IncomingUser1"), Some("ann secret67890"));
+
+        driver.del_attribute("IncomingUser", "joe secret12345")?;
+        assert_eq!(driver.attribute("IncomingUser"), None);
+        assert_eq!(driver.attribute("IncomingUser1"), Some("ann secret67890"));
+        assert_eq!(
+            echoed_cmds(),
+            vec![
+                "add_attribute IncomingUser ann secret67890",
+                "del_attribute IncomingUser joe secret12345",
+            ]
+        );
 
         Ok(())
     }
@@ -0,0 +1,250 @@
+//! helpers building fake sysfs trees for unit tests.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FIXTURE_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static ECHOED: RefCell<Vec<(PathBuf, String)>> = const { RefCell::new(Vec::new()) };
+    static SIMULATED: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// records a command written by `echo`, and applies it to the fixture if simulated.
+pub(crate) fn record(path: &Path, cmd: &str) {
+    ECHOED.with(|e| e.borrow_mut().push((path.to_path_buf(), cmd.to_string())));
+
+    let root = SIMULATED.with(|s| s.borrow().clone());
+    if let Some(root) = root {
+        let fx = Fixture { root, owned: false };
+        fx.apply(path, cmd);
+    }
+}
+
+/// takes all commands written by `echo` on the current thread.
+pub(crate) fn echoed() -> Vec<(PathBuf, String)> {
+    ECHOED.with(|e| e.borrow_mut().drain(..).collect())
+}
+
+/// takes only the commands written by `echo`, dropping the paths.
+pub(crate) fn echoed_cmds() -> Vec<String> {
+    echoed().into_iter().map(|(_, cmd)| cmd).collect()
+}
+
+/// a temporary directory laid out like the scst sysfs root.
+pub(crate) struct Fixture {
+    root: PathBuf,
+    owned: bool,
+}
+
+impl Fixture {
+    pub(crate) fn new(name: &str) -> Self {
+        let seq = FIXTURE_SEQ.fetch_add(1, Ordering::SeqCst);
+        let root =
+            std::env::temp_dir().join(format!("scst-{}-{}-{}", name, std::process::id(), seq));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        echoed();
+        SIMULATED.with(|s| *s.borrow_mut() = None);
+
+        Fixture { root, owned: true }
+    }
+
+    /// applies the commands written to `mgmt` files to the fixture like the kernel does, so
+    /// the created objects can be loaded back.
+    pub(crate) fn simulate(&self) -> &Self {
+        SIMULATED.with(|s| *s.borrow_mut() = Some(self.root.clone()));
+        self
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub(crate) fn dir<P: AsRef<Path>>(&self, path: P) -> &Self {
+        fs::create_dir_all(self.path(path)).unwrap();
+        self
+    }
+
+    pub(crate) fn file<P: AsRef<Path>>(&self, path: P, text: &str) -> &Self {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+        self
+    }
+
+    pub(crate) fn link<P: AsRef<Path>, Q: AsRef<Path>>(&self, path: P, target: Q) -> &Self {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        symlink(target, path).unwrap();
+        self
+    }
+
+    pub(crate) fn remove<P: AsRef<Path>>(&self, path: P) -> &Self {
+        let path = self.path(path);
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap();
+        } else {
+            fs::remove_file(path).unwrap();
+        }
+        self
+    }
+
+    /// lays out the scst root with the iscsi and copy_manager drivers.
+    pub(crate) fn scst(&self) -> &Self {
+        self.file("version", "3.7.0\n")
+            .dir("handlers")
+            .driver("iscsi")
+            .dir("targets/copy_manager/copy_manager_tgt/luns")
+            .dir("targets/copy_manager/copy_manager_tgt/ini_groups")
+            .file("targets/copy_manager/copy_manager_tgt/rel_tgt_id", "0\n")
+    }
+
+    pub(crate) fn handler(&self, handler: &str, r#type: &str) -> &Self {
+        self.file(format!("handlers/{}/type", handler), r#type)
+    }
+
+    pub(crate) fn device(&self, handler: &str, name: &str, filename: &str) -> &Self {
+        let dev = format!("handlers/{}/{}", handler, name);
+        if !self.path(format!("handlers/{}/type", handler)).exists() {
+            self.handler(handler, "0 - virtual\n");
+        }
+        self.link(
+            format!("{}/handler", dev),
+            self.path(format!("handlers/{}", handler)),
+        )
+        .file(format!("{}/filename", dev), &format!("{}\n", filename))
+        .file(format!("{}/active", dev), "1\n")
+        .file(format!("{}/read_only", dev), "0\n")
+        .file(format!("{}/size", dev), "10737418240\n")
+        .file(format!("{}/blocksize", dev), "512\n")
+    }
+
+    pub(crate) fn driver(&self, driver: &str) -> &Self {
+        let drv = format!("targets/{}", driver);
+        self.file(format!("{}/enabled", drv), "1\n")
+            .file(format!("{}/open_state", drv), "open\n")
+            .file(format!("{}/version", drv), "3.7.0\n")
+    }
+
+    /// lays out a target at `targets/<driver>/<name>`.
+    pub(crate) fn target(&self, driver: &str, name: &str) -> &Self {
+        let tgt = format!("targets/{}/{}", driver, name);
+        self.file(format!("{}/tid", tgt), "1\n")
+            .file(format!("{}/rel_tgt_id", tgt), "1\n")
+            .file(format!("{}/enabled", tgt), "1\n")
+            .dir(format!("{}/luns", tgt))
+            .dir(format!("{}/ini_groups", tgt))
+            .dir(format!("{}/sessions", tgt))
+    }
+
+    /// lays out an initiator group under the target directory `tgt`.
+    pub(crate) fn group(&self, tgt: &str, name: &str, initiators: &[&str]) -> &Self {
+        let grp = format!("{}/ini_groups/{}", tgt, name);
+        self.dir(format!("{}/luns", grp))
+            .dir(format!("{}/initiators", grp));
+        for ini in initiators {
+            self.file(format!("{}/initiators/{}", grp, ini), "");
+        }
+        self
+    }
+
+    /// lays out a lun under `parent`, which is a target or group directory.
+    pub(crate) fn lun(&self, parent: &str, id: u64, device: &str) -> &Self {
+        let lun = format!("{}/luns/{}", parent, id);
+        self.link(
+            format!("{}/device", lun),
+            self.path(format!("devices/{}", device)),
+        )
+        .file(format!("{}/read_only", lun), "0\n")
+    }
+
+    /// lays out a session under the target directory `tgt`.
+    pub(crate) fn session(&self, tgt: &str, name: &str, sid: &str) -> &Self {
+        let sess = format!("{}/sessions/{}", tgt, name);
+        self.file(format!("{}/sid", sess), &format!("{}\n", sid))
+            .file(format!("{}/thread_pid", sess), "1234\n")
+            .file(format!("{}/initiator_name", sess), &format!("{}\n", name))
+    }
+}
+
+impl Fixture {
+    fn apply(&self, path: &Path, cmd: &str) {
+        let rel = match path.strip_prefix(&self.root) {
+            Ok(rel) if rel.ends_with("mgmt") => rel,
+            _ => return,
+        };
+        let parts = rel
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        let args = cmd.split_whitespace().collect::<Vec<&str>>();
+        let parent = parts[..parts.len() - 1].join("/");
+        let owner = parts[..parts.len().saturating_sub(2)].join("/");
+        let kind = parts.get(parts.len().wrapping_sub(2)).map(|s| s.as_str());
+        let arg = |n: usize| args.get(n).copied().unwrap_or("");
+
+        match (parts.first().map(|s| s.as_str()), parts.len(), kind, arg(0)) {
+            (Some("handlers"), 3, _, "add_device") => {
+                let filename = args
+                    .iter()
+                    .find_map(|a| a.strip_prefix("filename="))
+                    .unwrap_or("");
+                self.device(&parts[1], arg(1), filename);
+                if args.contains(&"read_only=1") {
+                    self.file(format!("{}/{}/read_only", parent, arg(1)), "1\n");
+                }
+            }
+            (Some("handlers"), 3, _, "del_device") => {
+                self.remove(format!("{}/{}", parent, arg(1)));
+            }
+            (Some("targets"), 3, _, "add_target") => {
+                self.target(&parts[1], arg(1))
+                    .file(format!("{}/{}/enabled", parent, arg(1)), "0\n");
+            }
+            (Some("targets"), 3, _, "del_target") => {
+                self.remove(format!("{}/{}", parent, arg(1)));
+            }
+            (_, _, Some("ini_groups"), "create") => {
+                self.group(&owner, arg(1), &[]);
+            }
+            (_, _, Some("ini_groups"), "del") => {
+                self.remove(format!("{}/{}", parent, arg(1)));
+            }
+            (_, _, Some("luns"), "add") | (_, _, Some("luns"), "replace") => {
+                self.lun(&owner, arg(2).parse().unwrap(), arg(1));
+                if args.contains(&"read_only=1") {
+                    self.file(format!("{}/{}/read_only", parent, arg(2)), "1\n");
+                }
+            }
+            (_, _, Some("luns"), "del") => {
+                self.remove(format!("{}/{}", parent, arg(1)));
+            }
+            (_, _, Some("initiators"), "add") => {
+                self.file(format!("{}/{}", parent, arg(1)), "");
+            }
+            (_, _, Some("initiators"), "del") => {
+                self.remove(format!("{}/{}", parent, arg(1)));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if self.owned {
+            SIMULATED.with(|s| *s.borrow_mut() = None);
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}